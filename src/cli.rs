@@ -1,19 +1,114 @@
+use expr::{BlcIo, DeBruijn, Engine, Expr, Jets, KrivineReduction, LambdaError, LazyReduction, Notation, OptimalReduction, ReduceCache, ReduceConfig, Reduction, SkiReduction};
 use hashdb::LinkArena;
-use name::NamespaceMut;
+use name::{NamedObject, NamespaceMut};
 use parse::{command_parser, gen_report, parse};
 
 mod expr;
 mod name;
 mod parse;
+mod transpile;
 
 pub fn print_usage() {
-	println!("USAGE: tmp-lang <filename>")
+	println!("USAGE: tmp-lang [--notation <name>] <filename>")
 }
 
 pub fn read_from_file(filename: &str) -> Result<String, String> {
 	std::fs::read_to_string(filename).map_err(|_| "could not open file".into())
 }
 
+/// Columns results are wrapped to
+const LINE_WIDTH: usize = 80;
+/// File the normal forms found in a session are kept in for the next one
+const CACHE_FILE: &str = ".reduce_cache";
+
+/// How expressions are printed in a session
+struct Printer<'a, 'e> {
+	/// Subterms defined here are printed as their names
+	namespace: &'a NamespaceMut<'e>,
+	notation: Notation,
+}
+impl<'a, 'e> Printer<'a, 'e> {
+	/// Print expression with readable variable names, and the names of the definitions in it
+	fn expr(&self, expr: &'e Expr<'e>) {
+		match expr.pretty_named(self.namespace, self.notation, LINE_WIDTH) {
+			Ok(pretty) => println!("{pretty}"),
+			Err(_) => println!("{expr}"),
+		}
+	}
+	/// Print reduced expression, followed by the data it encodes if it is recognized
+	fn result(&self, expr: &'e Expr<'e>) {
+		self.expr(expr);
+		if let Some(decoded) = expr.decode_string() {
+			println!("= {decoded}");
+		}
+	}
+}
+
+/// Reduce expression and print the result, returns the partially reduced expression if it ran out of fuel
+fn print_reduction<'e>(expr: &'e Expr<'e>, engine: Engine, config: &ReduceConfig, cache: &ReduceCache<'e>, jets: Option<&Jets<'e>>, printer: &Printer<'_, 'e>, exprs: &'e LinkArena<'e>) -> Option<&'e Expr<'e>> {
+	if config.stats && matches!(engine, Engine::Optimal | Engine::Lazy | Engine::Krivine) {
+		println!("stats aren't measured by the {engine} engine, only by substitution and parallel");
+	}
+	let reduction = match engine {
+		Engine::Substitution => match jets {
+			Some(jets) => expr.reduce_jetted(config, cache, jets, exprs),
+			None => expr.reduce_cached(config, cache, exprs),
+		},
+		Engine::Parallel => expr.reduce_parallel(config, exprs),
+		Engine::Optimal => {
+			match expr.reduce_optimal(config, exprs) {
+				Ok(OptimalReduction { expr, betas, interactions }) => {
+					printer.result(expr);
+					println!("{betas} beta reductions, {interactions} interactions");
+				}
+				Err(err) => println!("error: {err}"),
+			}
+			return None;
+		}
+		Engine::Lazy => {
+			match expr.reduce_lazy(config, exprs) {
+				Ok(LazyReduction { expr, steps, reused }) => {
+					printer.result(expr);
+					println!("{steps} beta reductions, {reused} evaluated arguments reused");
+				}
+				Err(err) => println!("error: {err}"),
+			}
+			return None;
+		}
+		Engine::Krivine => {
+			match expr.reduce_krivine(config, exprs) {
+				Ok(KrivineReduction { expr, steps }) => {
+					printer.result(expr);
+					println!("{steps} beta reductions");
+				}
+				Err(err) => println!("error: {err}"),
+			}
+			return None;
+		}
+	};
+	match reduction {
+		Ok(Reduction { expr, out_of_fuel: false, stats, .. }) => {
+			printer.result(expr);
+			if let Some(stats) = stats { print!("{stats}") }
+			None
+		}
+		Ok(Reduction { expr, steps, stats, .. }) => {
+			printer.result(expr);
+			if let Some(stats) = stats { print!("{stats}") }
+			println!("ran out of fuel after {steps} steps, use `continue` to keep reducing");
+			Some(expr)
+		}
+		Err(LambdaError::Diverges(length)) => {
+			println!("this term loops, it comes back to the same expression every {length} beta reductions");
+			None
+		}
+		Err(err) => {
+			println!("error: {err}");
+			None
+		}
+	}
+}
+
 fn cli_editor() {
 	use ariadne::Source;
 	use chumsky::Parser;
@@ -22,33 +117,133 @@ fn cli_editor() {
 
 	println!("block-lang cli editor!");
 	let mut editor = Editor::<()>::new().unwrap();
-	if editor.load_history(".editor_history").is_err() {}
+	let _ = editor.load_history(".editor_history");
 
 	let namespace = NamespaceMut::new();
 	let exprs = LinkArena::new();
 	let binds = LinkArena::new();
 	let bind_map = parse::BindMap::default();
 	let cmdparser = command_parser(&namespace, &exprs, &binds, &bind_map);
-	loop {
-		let text = match editor.readline("=> ") {
-			Ok(line) => line,
-			Err(_) => break,
-		};
+	let mut config = ReduceConfig { stats: true, ..Default::default() };
+	let mut engine = Engine::default();
+	let cache = ReduceCache::new();
+	if let Ok(lines) = std::fs::read_to_string(CACHE_FILE) {
+		match cache.extend_from_bit_lines(&lines, &exprs) {
+			Ok(count) => println!("loaded {count} cached normal forms"),
+			Err(err) => println!("could not load {CACHE_FILE}: {err}"),
+		}
+	}
+	let jets = Jets::standard(&exprs);
+	let mut jets_enabled = true;
+	let mut printer = Printer { namespace: &namespace, notation: Notation::default() };
+	let mut unfinished = None;
+	while let Ok(text) = editor.readline("=> ") {
 		match cmdparser.parse(text.as_str()) {
 			Ok(Command::None) => {}
 			Ok(Command::Set(string, expr)) => {
-				println!("{expr}");
-				let reduced = expr.reduce(&exprs).unwrap();
-				println!("{reduced}");
-				namespace.add(string, reduced, &exprs);
+				printer.expr(expr);
+				match expr.reduce_cached(&config, &cache, &exprs).and_then(Reduction::finished) {
+					Ok(reduced) => {
+						printer.expr(reduced);
+						namespace.add(string, reduced, &exprs);
+					}
+					Err(err) => println!("error: {err}, `{string}` was not set"),
+				}
 			}
 			Ok(Command::List) => {
 				namespace.for_each(|name| println!("{name}"));
 			}
+			Ok(Command::Strategy(strategy)) => {
+				config.strategy = strategy;
+				println!("using {strategy} reduction strategy");
+			}
+			Ok(Command::Engine(selected)) => {
+				engine = selected;
+				println!("using {engine} engine");
+			}
+			Ok(Command::Fuel(fuel)) => {
+				config.fuel = Some(fuel);
+				println!("using fuel of {fuel} steps");
+			}
+			Ok(Command::Eta(eta)) => {
+				config.eta = eta;
+				println!("eta reduction {}", if eta { "enabled" } else { "disabled" });
+			}
+			Ok(Command::Stats(stats)) => {
+				config.stats = stats;
+				println!("reduction statistics {}", if stats { "enabled" } else { "disabled" });
+			}
+			Ok(Command::Jets(enabled)) => {
+				jets_enabled = enabled;
+				println!("jets {}", if enabled { "enabled" } else { "disabled" });
+			}
+			Ok(Command::Notation(notation)) => {
+				printer.notation = notation;
+				println!("printing in {notation} notation");
+			}
+			Ok(Command::Continue) => match unfinished.take() {
+				Some(expr) => unfinished = print_reduction(expr, engine, &config, &cache, jets_enabled.then_some(&jets), &printer, &exprs),
+				None => println!("no unfinished reduction to continue"),
+			},
+			Ok(Command::Trace { expr, file }) => match (expr.reduce_traced(&config, &exprs), file) {
+				(Ok(trace), None) => print!("{trace}"),
+				(Ok(trace), Some(file)) => match trace.to_bit_lines().map(|lines| std::fs::write(&file, lines)) {
+					Ok(Ok(())) => println!("wrote {} steps to {file}", trace.steps.len()),
+					Ok(Err(err)) => println!("error: could not write {file}: {err}"),
+					Err(err) => println!("error: {err}"),
+				},
+				(Err(err), _) => println!("error: {err}"),
+			},
+			Ok(Command::Blc(expr)) => match expr.to_debruijn() {
+				Ok(term) => {
+					let bits = term.to_bit_string();
+					println!("{bits}");
+					println!("{} bits", bits.len());
+				}
+				Err(err) => println!("error: {err}"),
+			},
+			Ok(Command::Ski(expr)) => match (expr.to_ski(false, config.eta), expr.to_ski(true, config.eta)) {
+				(Ok(ski), Ok(bcw)) => {
+					println!("S K I: {ski}");
+					println!("S K I B C W: {bcw}");
+					let SkiReduction { term, steps, out_of_fuel } = match bcw.reduce(&config) {
+						Ok(reduction) => reduction,
+						Err(err) => { println!("error: {err}"); continue }
+					};
+					println!("{term}");
+					if out_of_fuel {
+						println!("ran out of fuel after {steps} combinator reductions");
+					} else {
+						println!("{steps} combinator reductions");
+					}
+					match term.to_expr(&exprs).and_then(|expr| expr.reduce_with(&config, &exprs)).and_then(Reduction::finished) {
+						Ok(expr) => printer.result(expr),
+						Err(err) => println!("error: {err}"),
+					}
+				}
+				(Err(err), _) | (_, Err(err)) => println!("error: {err}"),
+			},
+			Ok(Command::Transpile { target, name, file }) => match namespace.find(|item| *item.string == name) {
+				Some(item) => match transpile::transpile(item, target) {
+					Ok(module) => match std::fs::write(&file, module) {
+						Ok(()) => println!("wrote {target} module for `{name}` to {file}"),
+						Err(err) => println!("error: could not write {file}: {err}"),
+					},
+					Err(err) => println!("error: {err}"),
+				},
+				None => println!("no definition named `{name}`"),
+			},
+			Ok(Command::Dot { name, file, bindings }) => match namespace.find(|item| *item.string == name).map(|item| &item.object) {
+				Some(&NamedObject::Expr(expr)) => match std::fs::write(&file, expr.to_dot(&name, bindings)) {
+					Ok(()) => println!("wrote graph of `{name}` to {file}"),
+					Err(err) => println!("error: could not write {file}: {err}"),
+				},
+				Some(&NamedObject::Namespace(_)) => println!("`{name}` is a namespace, not an expression"),
+				None => println!("no definition named `{name}`"),
+			},
 			Ok(Command::Reduce(expr)) => {
-				println!("{expr}");
-				let reduced = expr.reduce(&exprs).unwrap();
-				println!("{reduced}");
+				printer.expr(expr);
+				unfinished = print_reduction(expr, engine, &config, &cache, jets_enabled.then_some(&jets), &printer, &exprs);
 			}
 			// Ok(Command::Load { file }) => {
 			// 	todo!();
@@ -65,16 +260,23 @@ fn cli_editor() {
 		}
 	}
 	editor.save_history(".editor_history").unwrap();
+	if let Err(err) = cache.to_bit_lines().map_err(|err| err.to_string()).and_then(|lines| std::fs::write(CACHE_FILE, lines).map_err(|err| err.to_string())) {
+		println!("could not save {CACHE_FILE}: {err}");
+	}
 }
 
+/// Reduce the expression in a file and print its normal form, in the notation given by `--notation <name>`
 pub fn run_cli_args() -> Result<(), String> {
 	let mut input_files: Vec<String> = vec![];
-	for arg in std::env::args() {
+	let mut notation = Notation::default();
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
 		match arg.as_str() {
 			"-i" => {
 				cli_editor();
 				return Ok(());
 			}
+			"--notation" => notation = args.next().ok_or("missing notation after --notation")?.parse()?,
 			_ => input_files.push(arg),
 		};
 	}
@@ -85,12 +287,87 @@ pub fn run_cli_args() -> Result<(), String> {
 	}
 	let file_content =
 		read_from_file(input_files[0].as_str()).map_err(|_| "could not read file".to_owned())?;
-	let exprs = &LinkArena::new();
-	let namespace = &mut NamespaceMut::new();
-	let _parsed = parse(file_content.as_str(), namespace, exprs).unwrap();
+	println!("{}", file_output(&file_content, notation)?);
 	Ok(())
 }
 
+/// Normal form of the expression in a file printed in `notation`, files can be written in any notation
+fn file_output(file_content: &str, notation: Notation) -> Result<String, String> {
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let parsed = parse(file_content, namespace, exprs).map_err(|err| err.to_string())?;
+	let reduced = parsed.reduce_with(&ReduceConfig::default(), exprs).and_then(Reduction::finished).map_err(|err| err.to_string())?;
+	reduced.pretty(notation, LINE_WIDTH).map_err(|err| err.to_string())
+}
+
+/// Run binary lambda calculus program in `file` on stdin, writing its output to stdout
+fn run_blc(file: &str, io: BlcIo) -> Result<(), String> {
+	use std::io::{Read, Write};
+
+	let program = std::fs::read(file).map_err(|err| format!("could not read {file}: {err}"))?;
+	let mut input = Vec::new();
+	std::io::stdin().read_to_end(&mut input).map_err(|err| err.to_string())?;
+	let output = blc_output(&program, &input, io)?;
+	std::io::stdout().write_all(&output).map_err(|err| err.to_string())
+}
+
+/// Output of binary lambda calculus `program` run on `input`.
+/// Programs are written as `0` and `1` characters for `BlcIo::Bits` and packed into bytes for `BlcIo::Bytes`.
+fn blc_output(program: &[u8], input: &[u8], io: BlcIo) -> Result<Vec<u8>, String> {
+	let term = match io {
+		BlcIo::Bits => DeBruijn::from_bit_string(&String::from_utf8_lossy(program)),
+		BlcIo::Bytes => DeBruijn::from_bytes(program),
+	}.map_err(|err| err.to_string())?;
+	let exprs = &LinkArena::new();
+	// Input is one list nested as deep as it is long, so the work stack can't be limited
+	let config = &ReduceConfig { fuel: None, max_size: None, recursion_limit: usize::MAX, ..Default::default() };
+	let program = term.to_expr(exprs).map_err(|err| err.to_string())?;
+	program.run_blc(input, io, config, exprs).map_err(|err| err.to_string())
+}
+
 fn main() {
-	cli_editor();
+	let args: Vec<String> = std::env::args().collect();
+	let io = match args.get(1).map(String::as_str) {
+		Some("--blc") => BlcIo::Bits,
+		Some("--blc8") => BlcIo::Bytes,
+		None => return cli_editor(),
+		Some(_) => {
+			if let Err(err) = run_cli_args() {
+				eprintln!("error: {err}");
+				print_usage();
+				std::process::exit(1);
+			}
+			return;
+		}
+	};
+	let Some(file) = args.get(2) else {
+		eprintln!("USAGE: tmp-lang --blc <program> or tmp-lang --blc8 <program>, with input on stdin");
+		std::process::exit(1);
+	};
+	if let Err(err) = run_blc(file, io) {
+		eprintln!("error: {err}");
+		std::process::exit(1);
+	}
+}
+
+#[test]
+fn blc_output_test() {
+	// `cat`, with inputs nested deeper than any fixed recursion limit would allow
+	let bits: Vec<u8> = (0..8000).map(|i| if i % 3 == 0 { b'1' } else { b'0' }).collect();
+	assert_eq!(blc_output(b"0010", &bits, BlcIo::Bits).unwrap(), bits);
+	let bytes: Vec<u8> = (0..8000).map(|i| (i % 251) as u8).collect();
+	assert_eq!(blc_output(&[0b0010_0000], &bytes, BlcIo::Bytes).unwrap(), bytes);
+	assert!(blc_output(b"0012", &bits, BlcIo::Bits).is_err());
+}
+
+#[test]
+fn file_output_test() {
+	// Files can be read in one notation and printed in another
+	let file = "(\\f x. f (f x)) (\\f x. f (f x))";
+	assert_eq!(file_output(file, Notation::Brackets).unwrap(), file_output("([f x] f (f x)) (fun f x -> f (f x))", Notation::Brackets).unwrap());
+	let reduced = file_output(file, Notation::Lambda).unwrap();
+	assert!(reduced.starts_with("λ"), "{reduced}");
+	assert_eq!(file_output(&reduced, Notation::Lambda).unwrap(), reduced);
+	assert!(file_output(file, Notation::Latex).unwrap().starts_with("\\lambda "));
+	assert!(file_output("[x", Notation::Lambda).is_err());
 }
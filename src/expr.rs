@@ -2,15 +2,47 @@
 
 use bytecheck::CheckBytes;
 use rkyv::{Archive, Deserialize, Serialize};
-use std::fmt;
+use std::{fmt, hash::{Hash, Hasher}};
 use thiserror::Error;
 
 use hashdb::{ArchiveDeserializer, ArchiveStore, HashType, LinkArena, TypeStore};
 
 mod bind;
+mod blc;
+mod cache;
+mod debruijn;
+mod decode;
+mod dot;
+mod equiv;
+mod eta;
+mod jets;
+mod krivine;
+mod lazy;
+mod optimal;
+mod parallel;
+mod pretty;
 mod reduce;
+mod ski;
+mod trace;
 pub use bind::*;
+pub use blc::*;
+pub use cache::*;
+pub use debruijn::*;
+#[allow(unused_imports)]
+pub use decode::*;
+#[allow(unused_imports)]
+pub use dot::*;
+#[allow(unused_imports)]
+pub use equiv::*;
+pub use jets::*;
+pub use krivine::*;
+pub use lazy::*;
+pub use optimal::*;
+pub use pretty::*;
 pub use reduce::*;
+pub use ski::*;
+#[allow(unused_imports)]
+pub use trace::*;
 
 #[derive(Error, Debug)]
 pub enum LambdaError {
@@ -18,12 +50,27 @@ pub enum LambdaError {
 	#[error("recursion depth for beta reduction exceeded")]
 	RecursionDepthExceeded,
 
+	#[error("ran out of fuel after {0} beta reductions")]
+	OutOfFuel(usize),
+
+	#[error("expression loops, it reduces back to itself every {0} beta reductions")]
+	Diverges(usize),
+
+	#[error("beta reduction produced expression larger than the maximum size of {0} nodes")]
+	SizeLimitExceeded(usize),
+
 	#[error("binding level mismatch: make sure variable bindings match with variable positions in expressions and that bindings don't overlap")]
 	BindingLevelMismatch,
 
 	#[error("found variable in expression but binding tree is branching")]
 	UnexpectedBranchInSubstitution,
 
+	#[error("de Bruijn indices start at 1, found 0")]
+	ZeroDeBruijnIndex,
+
+	#[error("invalid interaction net: {0}")]
+	InvalidNet(&'static str),
+
 	#[error("bind error: {0}")]
 	BindError(#[from] BindTreeError)
 }
@@ -59,21 +106,51 @@ impl<'a> fmt::Display for &'a Expr<'a> {
 		thread_local! {
 			static BINDS: LinkArena<'static> = LinkArena::new();
 		}
-		match self {
-			Expr::Variable => write!(f, "x")?,
-			Expr::Lambda { .. } => {
-				BINDS.with(|reps| {
-					let mut index = BindIndex::DEFAULT;
-					let expr = index.push_lambda(&self, reps).unwrap();
-					write!(f, "(λ{}[{}] {})", index.index, index.tree, expr)
-				})?;
+		// Written using an explicit stack so deeply nested expressions don't overflow
+		enum Token<'a> {
+			Expr(&'a Expr<'a>),
+			Str(&'static str),
+		}
+		let mut tokens = vec![Token::Expr(self)];
+		while let Some(token) = tokens.pop() {
+			match token {
+				Token::Str(string) => f.write_str(string)?,
+				Token::Expr(Expr::Variable) => write!(f, "x")?,
+				Token::Expr(lambda @ Expr::Lambda { .. }) => {
+					BINDS.with(|reps| {
+						let mut index = BindIndex::DEFAULT;
+						let expr = index.push_lambda(lambda, reps).unwrap();
+						tokens.push(Token::Str(")"));
+						tokens.push(Token::Expr(expr));
+						write!(f, "(λ{}[{}] ", index.index, index.tree)
+					})?;
+				}
+				Token::Expr(Expr::Application { func, args: sub }) => {
+					tokens.extend([Token::Str(")"), Token::Expr(sub), Token::Str(" "), Token::Expr(func)]);
+					f.write_str("(")?;
+				}
 			}
-			Expr::Application { func, args: sub } => write!(f, "({} {})", func, sub)?,
 		}
 		Ok(())
 	}
 }
 
+/// Reference that is hashed and compared by address instead of by content.
+/// Expressions and bindings are hash-consed, so within an arena this is the same as comparing them by content, without walking the whole term.
+#[derive(Debug)]
+pub struct ByAddress<'a, T>(pub &'a T);
+impl<'a, T> Clone for ByAddress<'a, T> {
+	fn clone(&self) -> Self { *self }
+}
+impl<'a, T> Copy for ByAddress<'a, T> {}
+impl<'a, T> PartialEq for ByAddress<'a, T> {
+	fn eq(&self, other: &Self) -> bool { std::ptr::eq(self.0, other.0) }
+}
+impl<'a, T> Eq for ByAddress<'a, T> {}
+impl<'a, T> Hash for ByAddress<'a, T> {
+	fn hash<H: Hasher>(&self, state: &mut H) { std::ptr::hash(self.0, state) }
+}
+
 impl<'a> Expr<'a> {
 	pub const VAR: 	&'static Expr<'static> = &Expr::Variable;
 	pub fn lambda(bind: &'a Binding<'a>, expr: &'a Expr<'a>, arena: &'a impl TypeStore<'a>) -> &'a Expr<'a> {
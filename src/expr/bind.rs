@@ -1,4 +1,4 @@
-///! Bindings allow for more easy manipulation of Lambda abstractions during beta reduction and creation
+//! Bindings allow for more easy manipulation of Lambda abstractions during beta reduction and creation
 use std::fmt;
 use thiserror::Error;
 
@@ -91,9 +91,11 @@ impl<'a, 'e, T: TypeStorable + 'e> BindTree<'a, T> {
 	pub fn branch(left: &'a Self, right: &'a Self, trees: &'a impl TypeStore<'a>) -> &'a Self {
 		trees.add(Self::branch_new(left, right))
 	}
+	#[allow(dead_code)]
 	pub fn left(&'a self, trees: &'a impl TypeStore<'a>) -> &'a Self {
 		Self::branch(self, BindTree::NONE, trees)
 	}
+	#[allow(dead_code)]
 	pub fn right(&'a self, trees: &'a impl TypeStore<'a>) -> &'a Self {
 		Self::branch(BindTree::NONE, self, trees)
 	}
@@ -101,57 +103,100 @@ impl<'a, 'e, T: TypeStorable + 'e> BindTree<'a, T> {
 		trees.add(BindTree::End(val))
 	}
 	/// Add PointerTree to ReplaceTree at certain abstraction level
+	/// Uses an explicit stack so that deep trees don't overflow.
 	pub fn push_binding(self: &mut &'a Self, trees: &'a impl TypeStore<'a>, end: T, binds: &'e Binding<'e>) -> Result<(), BindTreeError>
 		where T: Clone,
 	{
-		*self = match (*self, binds) {
-			// If ReplaceTree is None, fill in binds
-			(tree, Binding::None) => tree,
-			(BindTree::None, Binding::End) => Self::end(end, trees),
-			(BindTree::None, Binding::Branch(l, r)) => {
-				let (mut left, mut right) = (Self::NONE, Self::NONE);
-				left.push_binding(trees, end.clone(), l)?;
-				right.push_binding(trees, end, r)?;
-				Self::branch(left, right, trees)
-			}
-			(BindTree::Branch(mut left, mut right), Binding::Branch(l, r)) => {
-				left.push_binding(trees, end.clone(), l)?;
-				right.push_binding(trees, end, r)?;
-				Self::branch(left, right, trees)
+		enum Task<'a, 'e, T: TypeStorable> {
+			Push(&'a BindTree<'a, T>, &'e Binding<'e>),
+			Branch,
+		}
+		let mut tasks = vec![Task::Push(*self, binds)];
+		let mut pushed = Vec::new();
+		while let Some(task) = tasks.pop() {
+			match task {
+				Task::Push(tree, binds) => match (tree, binds) {
+					// If ReplaceTree is None, fill in binds
+					(tree, Binding::None) => pushed.push(tree),
+					(BindTree::None, Binding::End) => pushed.push(Self::end(end.clone(), trees)),
+					(BindTree::None, Binding::Branch(l, r)) => {
+						tasks.push(Task::Branch);
+						tasks.push(Task::Push(Self::NONE, r));
+						tasks.push(Task::Push(Self::NONE, l));
+					}
+					(BindTree::Branch(left, right), Binding::Branch(l, r)) => {
+						tasks.push(Task::Branch);
+						tasks.push(Task::Push(right, r));
+						tasks.push(Task::Push(left, l));
+					}
+					(BindTree::End(_), _) => return Err(BindTreeError::AlreadyBound),
+					(_, Binding::End) => return Err(BindTreeError::InvalidBindLocation),
+				}
+				Task::Branch => {
+					let right = pushed.pop().unwrap();
+					let left = pushed.pop().unwrap();
+					pushed.push(Self::branch(left, right, trees));
+				}
 			}
-			(BindTree::End(_), _) => return Err(BindTreeError::AlreadyBound),
-			(_, Binding::End) => return Err(BindTreeError::InvalidBindLocation),
-		};
+		}
+		*self = pushed.pop().unwrap();
 		Ok(())
 	}
 	/// Constructs PointerTree from ReplaceTree at certain abstraction level
+	/// Uses an explicit stack so that deep trees don't overflow.
 	pub fn pop_binding(self: &mut &'a Self, trees: &'a impl TypeStore<'a>, end: &T, binds: &'e impl TypeStore<'e>) -> Result<&'e Binding<'e>, BindTreeError> 
 		where T: PartialEq,
 	{
-		Ok(match self {
-			BindTree::Branch(mut l, mut r) => {
-				let left = l.pop_binding(trees, end, binds)?;
-				let right = r.pop_binding(trees, end, binds)?;
-				*self = Self::branch(l, r, trees);
-				Binding::branch_reduce(left, right, binds)
-			}
-			BindTree::End(count) if *end == *count => {
-				*self = Self::NONE;
-				Binding::END
+		enum Task<'a, T: TypeStorable> {
+			Pop(&'a BindTree<'a, T>),
+			Branch,
+		}
+		let mut tasks = vec![Task::Pop(*self)];
+		let mut popped: Vec<(&'a Self, &'e Binding<'e>)> = Vec::new();
+		while let Some(task) = tasks.pop() {
+			match task {
+				Task::Pop(tree) => match tree {
+					BindTree::Branch(l, r) => {
+						tasks.push(Task::Branch);
+						tasks.push(Task::Pop(r));
+						tasks.push(Task::Pop(l));
+					}
+					BindTree::End(count) if *end == *count => popped.push((Self::NONE, Binding::END)),
+					_ => popped.push((tree, Binding::NONE)),
+				}
+				Task::Branch => {
+					let (r, right) = popped.pop().unwrap();
+					let (l, left) = popped.pop().unwrap();
+					popped.push((Self::branch(l, r, trees), Binding::branch_reduce(left, right, binds)));
+				}
 			}
-			_ => Binding::NONE,
-		})
+		}
+		let (tree, binding) = popped.pop().unwrap();
+		*self = tree;
+		Ok(binding)
 	}
 }
 
 impl<'a, T: fmt::Display + TypeStorable> fmt::Display for BindTree<'a, T> {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		match self {
-			BindTree::Branch(BindTree::None, right) => write!(f, ">{}", right)?,
-			BindTree::Branch(left, BindTree::None) => write!(f, "<{}", left)?,
-			BindTree::Branch(left, right) => write!(f, "({},{})", left, right)?,
-			BindTree::End(val) => write!(f, "{}", val)?,
-			BindTree::None => write!(f, "N")?,
+		// Written using an explicit stack so deep trees don't overflow
+		enum Token<'t, 'a, T: TypeStorable> {
+			Tree(&'t BindTree<'a, T>),
+			Str(&'static str),
+		}
+		let mut tokens = vec![Token::Tree(self)];
+		while let Some(token) = tokens.pop() {
+			match token {
+				Token::Str(string) => f.write_str(string)?,
+				Token::Tree(BindTree::Branch(BindTree::None, right)) => { write!(f, ">")?; tokens.push(Token::Tree(right)) },
+				Token::Tree(BindTree::Branch(left, BindTree::None)) => { write!(f, "<")?; tokens.push(Token::Tree(left)) },
+				Token::Tree(BindTree::Branch(left, right)) => {
+					write!(f, "(")?;
+					tokens.extend([Token::Str(")"), Token::Tree(right), Token::Str(","), Token::Tree(left)]);
+				}
+				Token::Tree(BindTree::End(val)) => write!(f, "{}", val)?,
+				Token::Tree(BindTree::None) => write!(f, "N")?,
+			}
 		}
 		Ok(())
 	}
@@ -201,19 +246,24 @@ impl<'a> BindIndex<'a> {
 		*index -= 1;
 		Ok(ret)
 	}
-	/// Build BindIndex from nested Lambda expressions
+	/// Build BindIndex from nested Lambda expressions, innermost lambda is pushed first
 	pub fn push_lambda<'e>(&mut self, expr: &'e Expr<'e>, trees: &'a impl TypeStore<'a>) -> Result<&'e Expr<'e>, BindTreeError> {
-		Ok(if let Expr::Lambda { bind: binds_tree, expr } = expr {
-			let pushed_expr = self.push_lambda(expr, trees)?;
-			self.push_binding(binds_tree, trees)?;
-			pushed_expr
-		} else { &expr })
+		let mut binds = Vec::new();
+		let mut pushed_expr = expr;
+		while let Expr::Lambda { bind, expr } = pushed_expr {
+			binds.push(*bind);
+			pushed_expr = expr;
+		}
+		for bind in binds.into_iter().rev() {
+			self.push_binding(bind, trees)?;
+		}
+		Ok(pushed_expr)
 	}
 	/// Creates nested Lambda expressions from BindIndex
 	#[allow(dead_code)]
 	pub fn pop_lambda<'e>(&mut self, expr: &'e Expr<'e>, trees: &'a impl TypeStore<'a>, exprs: &'e impl TypeStore<'e>) -> Result<&'e Expr<'e>, LambdaError> {
 		let binds_tree = self.pop_binding(trees, exprs)?;
-		let popped_expr = if self.index == 0 { &expr } else { self.pop_lambda(expr, trees, exprs)? };
+		let popped_expr = if self.index == 0 { expr } else { self.pop_lambda(expr, trees, exprs)? };
 		Ok(Expr::lambda(binds_tree, popped_expr, exprs))
 	}
 }
@@ -0,0 +1,194 @@
+//! Tromp's binary lambda calculus: terms written as bits, and programs run on lists of bits or bytes
+
+use hashdb::TypeStore;
+use thiserror::Error;
+
+use super::{decode::{church_bool, pair}, Binding, DeBruijn, DeBruijnNode, Expr, LambdaError, ReduceConfig};
+
+/// Errors reading binary lambda calculus or running programs
+#[derive(Debug, Error)]
+pub enum BlcError {
+	#[error("bits ended in the middle of a term")]
+	UnexpectedEnd,
+	#[error("invalid character `{0}` in bit string, expected `0` or `1`")]
+	InvalidCharacter(char),
+	#[error("{0} bits left after the end of the term")]
+	TrailingBits(usize),
+	#[error("invalid step `{0}` in redex path, expected `b`, `f`, `a` or `.`")]
+	InvalidPathStep(char),
+	#[error("output is not a list of {0}")]
+	InvalidOutput(&'static str),
+	#[error("lambda error: {0}")]
+	LambdaError(#[from] LambdaError),
+}
+
+impl DeBruijn {
+	/// Lambda is `00`, application is `01` and variable `n` is `n` ones followed by a zero
+	pub fn to_bits(&self) -> Vec<bool> {
+		let mut bits = Vec::new();
+		for node in self.nodes() {
+			match *node {
+				DeBruijnNode::Lambda => bits.extend([false, false]),
+				DeBruijnNode::App => bits.extend([false, true]),
+				DeBruijnNode::Var(index) => {
+					bits.extend((0..index).map(|_| true));
+					bits.push(false);
+				}
+			}
+		}
+		bits
+	}
+	/// Read one term from the start of `bits`
+	fn read_bits(bits: &mut impl Iterator<Item = bool>) -> Result<Self, BlcError> {
+		let mut nodes = Vec::new();
+		let mut next = || bits.next().ok_or(BlcError::UnexpectedEnd);
+		// Number of subterms still to read
+		let mut missing = 1;
+		while missing > 0 {
+			missing -= 1;
+			nodes.push(match (next()?, next()?) {
+				(false, false) => { missing += 1; DeBruijnNode::Lambda }
+				(false, true) => { missing += 2; DeBruijnNode::App }
+				(true, mut bit) => {
+					let mut index = 1;
+					while bit {
+						index += 1;
+						bit = next()?;
+					}
+					DeBruijnNode::Var(index)
+				}
+			});
+		}
+		Ok(Self { nodes })
+	}
+	pub fn from_bits(bits: &[bool]) -> Result<Self, BlcError> {
+		let mut bits = bits.iter().copied();
+		let term = Self::read_bits(&mut bits)?;
+		match bits.count() {
+			0 => Ok(term),
+			trailing => Err(BlcError::TrailingBits(trailing)),
+		}
+	}
+	/// Bits written as `0` and `1` characters
+	pub fn to_bit_string(&self) -> String {
+		self.to_bits().into_iter().map(|bit| if bit { '1' } else { '0' }).collect()
+	}
+	/// Read bits written as `0` and `1` characters, whitespace is ignored
+	pub fn from_bit_string(string: &str) -> Result<Self, BlcError> {
+		let bits = string.chars().filter(|c| !c.is_whitespace()).map(|c| match c {
+			'0' => Ok(false),
+			'1' => Ok(true),
+			c => Err(BlcError::InvalidCharacter(c)),
+		}).collect::<Result<Vec<_>, _>>()?;
+		Self::from_bits(&bits)
+	}
+	/// Bits packed into bytes with the highest bit first, the last byte is padded with zeros
+	pub fn to_bytes(&self) -> Vec<u8> {
+		self.to_bits().chunks(8).map(|chunk| chunk.iter().enumerate().fold(0, |byte, (i, bit)| byte | (*bit as u8) << (7 - i))).collect()
+	}
+	/// Read bits packed into bytes, bits after the term in its last byte are padding
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, BlcError> {
+		let mut bits = bytes.iter().flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1));
+		let term = Self::read_bits(&mut bits)?;
+		match bits.count() {
+			trailing if trailing >= 8 => Err(BlcError::TrailingBits(trailing)),
+			_ => Ok(term),
+		}
+	}
+}
+
+/// How input and output of programs are represented. Lists are nested pairs `[p] p head tail` ending in `[x y] y`, a zero bit is `[x y] x` and a one bit is `[x y] y`.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub enum BlcIo {
+	/// List of bits. Input bits are the lowest bits of the input bytes, so `0` and `1` characters can be used (whitespace is skipped), and output bits are written as `0` and `1` characters.
+	Bits,
+	/// List of bytes, which are lists of 8 bits with the highest first
+	Bytes,
+}
+impl BlcIo {
+	fn encode<'e>(self, input: &[u8], exprs: &'e impl TypeStore<'e>) -> &'e Expr<'e> {
+		match self {
+			Self::Bits => list(input.iter().filter(|byte| !byte.is_ascii_whitespace()).map(|byte| bit(byte & 1 == 1, exprs)).collect(), exprs),
+			Self::Bytes => list(input.iter().map(|byte| list((0..8).rev().map(|i| bit(byte >> i & 1 == 1, exprs)).collect(), exprs)).collect(), exprs),
+		}
+	}
+	fn decode(self, output: &Expr) -> Result<Vec<u8>, BlcError> {
+		let bits = |expr| read_list(expr)?.into_iter().map(read_bit).collect::<Option<Vec<bool>>>();
+		match self {
+			Self::Bits => bits(output).map(|bits| bits.into_iter().map(|bit| if bit { b'1' } else { b'0' }).collect()).ok_or(BlcError::InvalidOutput("bits")),
+			Self::Bytes => read_list(output).and_then(|bytes| bytes.into_iter().map(|byte| {
+				let bits = bits(byte).filter(|bits| bits.len() == 8)?;
+				Some(bits.into_iter().fold(0, |byte, bit| byte << 1 | bit as u8))
+			}).collect()).ok_or(BlcError::InvalidOutput("bytes")),
+		}
+	}
+}
+
+/// `[x y] x` for zero and `[x y] y` for one
+fn bit<'e>(one: bool, exprs: &'e impl TypeStore<'e>) -> &'e Expr<'e> {
+	let (bind_x, bind_y) = if one { (Binding::NONE, Binding::END) } else { (Binding::END, Binding::NONE) };
+	Expr::lambda(bind_x, Expr::lambda(bind_y, Expr::VAR, exprs), exprs)
+}
+fn read_bit(expr: &Expr) -> Option<bool> {
+	church_bool(expr).map(|zero| !zero)
+}
+/// Items as pairs `[p] p head tail` ending in `[x y] y`
+fn list<'e>(items: Vec<&'e Expr<'e>>, exprs: &'e impl TypeStore<'e>) -> &'e Expr<'e> {
+	let bind = Binding::branch_reduce(Binding::branch_reduce(Binding::END, Binding::NONE, exprs), Binding::NONE, exprs);
+	items.into_iter().rev().fold(bit(true, exprs), |tail, head| {
+		Expr::lambda(bind, Expr::app(Expr::app(Expr::VAR, head, exprs), tail, exprs), exprs)
+	})
+}
+fn read_list<'e>(mut expr: &'e Expr<'e>) -> Option<Vec<&'e Expr<'e>>> {
+	let mut items = Vec::new();
+	while church_bool(expr) != Some(false) {
+		let (head, tail) = pair(expr)?;
+		items.push(head);
+		expr = tail;
+	}
+	Some(items)
+}
+
+impl<'e> Expr<'e> {
+	/// Apply program to `input` and read the normal form back as output
+	pub fn run_blc(self: &'e Expr<'e>, input: &[u8], io: BlcIo, config: &ReduceConfig, exprs: &'e impl TypeStore<'e>) -> Result<Vec<u8>, BlcError> {
+		let output = Expr::app(self, io.encode(input, exprs), exprs).reduce_with(config, exprs)?.finished()?;
+		io.decode(output)
+	}
+}
+
+#[test]
+fn blc_test() {
+	use hashdb::LinkArena;
+	use crate::{name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let term = |string| parse(string, namespace, exprs).unwrap().to_debruijn().unwrap();
+
+	// Terms from Tromp's papers
+	assert_eq!(term("[x] x").to_bit_string(), "0010");
+	assert_eq!(term("[x y] x").to_bit_string(), "0000110");
+	assert_eq!(term("[x y z] x z (y z)").to_bit_string(), "00000001011110100111010");
+	for string in ["[x y z] x z (y z)", "[f] ([x] f (x x)) ([x] f (x x))", "[x] y x"] {
+		let term = term(string);
+		assert_eq!(DeBruijn::from_bit_string(&term.to_bit_string()).unwrap(), term);
+		assert_eq!(DeBruijn::from_bytes(&term.to_bytes()).unwrap(), term);
+	}
+	assert_eq!(term("[x y z] x z (y z)").to_bytes(), [0b00000001, 0b01111010, 0b01110100]);
+	assert!(matches!(DeBruijn::from_bit_string("0000 11"), Err(BlcError::UnexpectedEnd)));
+	assert!(matches!(DeBruijn::from_bit_string("00102"), Err(BlcError::InvalidCharacter('2'))));
+	assert!(matches!(DeBruijn::from_bit_string("001010"), Err(BlcError::TrailingBits(2))));
+	assert!(matches!(DeBruijn::from_bytes(&[0b00100000, 0]), Err(BlcError::TrailingBits(12))));
+
+	let config = &ReduceConfig::default();
+	let run = |program: &str, input: &[u8], io| DeBruijn::from_bit_string(program).unwrap().to_expr(exprs).unwrap().run_blc(input, io, config, exprs).unwrap();
+	// Identity program copies its input
+	assert_eq!(run("0010", b"0110", BlcIo::Bits), b"0110");
+	assert_eq!(run("0010", b"01 10\n", BlcIo::Bits), b"0110");
+	assert_eq!(run("0010", b"Hi!", BlcIo::Bytes), b"Hi!");
+	// Tail of the input
+	assert_eq!(run(&term("[l] l ([h t] t)").to_bit_string(), b"Hi!", BlcIo::Bytes), b"i!");
+	// Prepend a one bit
+	assert_eq!(run(&term("[l] [p] p ([x y] y) l").to_bit_string(), b"00", BlcIo::Bits), b"100");
+}
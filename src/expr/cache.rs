@@ -0,0 +1,127 @@
+//! Memoization of normal forms, so that reducing the same expression twice doesn't redo the work
+
+use std::{cell::RefCell, collections::HashMap};
+
+use bytecheck::CheckBytes;
+use rkyv::{with::Map, Archive, Deserialize, Serialize};
+
+use hashdb::{ArchiveDeserializer, ArchiveStore, HashType, LinkArena, TypeStore};
+
+use super::{BlcError, ByAddress, DeBruijn, Expr};
+
+/// Expression linked to its normal form
+#[derive(Debug, Hash, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+#[archive(bound(serialize = "__S: ArchiveStore", deserialize = "__D: ArchiveDeserializer<'e>"))]
+pub struct NormalForm<'e> {
+	#[with(HashType)]
+	#[omit_bounds]
+	pub expr: &'e Expr<'e>,
+	#[with(HashType)]
+	#[omit_bounds]
+	pub reduced: &'e Expr<'e>,
+}
+
+/// List of known normal forms, the storable version of `ReduceCache`
+#[derive(Clone, Hash, Debug, Archive, Serialize, Deserialize, Default)]
+#[archive_attr(derive(CheckBytes))]
+#[archive(bound(serialize = "__S: ArchiveStore", deserialize = "__D: ArchiveDeserializer<'e>"))]
+pub struct NormalForms<'e> {
+	#[with(Map<HashType>)]
+	#[omit_bounds]
+	pub items: Vec<&'e NormalForm<'e>>,
+}
+
+/// Maps expressions (by address, as they are hash-consed) to their normal forms.
+/// Consulted during normal order reduction for every closed application, so shared sub-reductions are only done once.
+#[derive(Default)]
+pub struct ReduceCache<'e> {
+	map: RefCell<HashMap<ByAddress<'e, Expr<'e>>, &'e Expr<'e>>>,
+}
+impl<'e> ReduceCache<'e> {
+	pub fn new() -> Self { Self::default() }
+	/// Get cached normal form of an expression
+	pub fn get(&self, expr: &'e Expr<'e>) -> Option<&'e Expr<'e>> {
+		self.map.borrow().get(&ByAddress(expr)).copied()
+	}
+	/// Record the normal form of an expression
+	pub fn insert(&self, expr: &'e Expr<'e>, reduced: &'e Expr<'e>) {
+		self.map.borrow_mut().insert(ByAddress(expr), reduced);
+	}
+	pub fn len(&self) -> usize { self.map.borrow().len() }
+	pub fn is_empty(&self) -> bool { self.map.borrow().is_empty() }
+	pub fn clear(&self) { self.map.borrow_mut().clear() }
+	/// Store cached normal forms in arena, so they can be saved and loaded in another session
+	pub fn store_inner(&self, exprs: &'e LinkArena<'e>) -> &'e NormalForms<'e> {
+		let items = self.map.borrow().iter().map(|(&ByAddress(expr), &reduced)| exprs.add(NormalForm { expr, reduced })).collect();
+		exprs.add(NormalForms { items })
+	}
+	/// Add previously stored normal forms to the cache
+	pub fn extend(&self, forms: &NormalForms<'e>) {
+		self.map.borrow_mut().extend(forms.items.iter().map(|form| (ByAddress(form.expr), form.reduced)));
+	}
+	/// Write cached normal forms as lines of two binary lambda calculus bit strings, so they can be read in another session.
+	/// Only closed expressions are cached, so their de Bruijn terms convert back to the same expressions.
+	pub fn to_bit_lines(&self) -> Result<String, BlcError> {
+		let mut lines = String::new();
+		for (&ByAddress(expr), &reduced) in self.map.borrow().iter() {
+			lines.push_str(&expr.to_debruijn()?.to_bit_string());
+			lines.push(' ');
+			lines.push_str(&reduced.to_debruijn()?.to_bit_string());
+			lines.push('\n');
+		}
+		Ok(lines)
+	}
+	/// Add normal forms written by `to_bit_lines` to the cache, returns how many were read
+	pub fn extend_from_bit_lines(&self, lines: &str, exprs: &'e impl TypeStore<'e>) -> Result<usize, BlcError> {
+		let mut count = 0;
+		for line in lines.lines().filter(|line| !line.trim().is_empty()) {
+			let (expr, reduced) = line.split_once(' ').ok_or(BlcError::UnexpectedEnd)?;
+			let expr = DeBruijn::from_bit_string(expr)?.to_expr(exprs)?;
+			let reduced = DeBruijn::from_bit_string(reduced)?.to_expr(exprs)?;
+			self.insert(expr, reduced);
+			count += 1;
+		}
+		Ok(count)
+	}
+}
+impl<'e> From<&NormalForms<'e>> for ReduceCache<'e> {
+	fn from(forms: &NormalForms<'e>) -> Self {
+		let cache = ReduceCache::new();
+		cache.extend(forms);
+		cache
+	}
+}
+
+#[test]
+fn reduce_cache_test() {
+	use crate::{expr::{ReduceConfig, Reduction}, name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let config = &ReduceConfig::default();
+	let cache = &ReduceCache::new();
+
+	let iszero = parse("[n] n ([u] [x y] y) ([x y] x)", namespace, exprs).unwrap();
+	namespace.add("iszero", iszero, exprs);
+	let expr = parse("[z] z (iszero ([f x] f x)) (iszero ([f x] f x))", namespace, exprs).unwrap();
+
+	// Second (identical) argument is looked up instead of reduced
+	let reduction = expr.reduce_cached(config, cache, exprs).unwrap();
+	assert_eq!(reduction.expr, parse("[z] z ([x y] y) ([x y] y)", namespace, exprs).unwrap());
+	assert_eq!(reduction.steps, expr.reduce_with(config, exprs).unwrap().steps / 2);
+
+	// Cached normal forms survive being stored and loaded
+	let stored = cache.store_inner(exprs);
+	let loaded = &ReduceCache::from(stored);
+	assert_eq!(loaded.len(), cache.len());
+	let reduction = expr.reduce_cached(config, loaded, exprs).unwrap();
+	assert_eq!(reduction, Reduction { expr: reduction.expr, steps: 0, out_of_fuel: false, stats: None });
+
+	// and being written to text and read back, as the cli does between sessions
+	let loaded = &ReduceCache::new();
+	assert_eq!(loaded.extend_from_bit_lines(&cache.to_bit_lines().unwrap(), exprs).unwrap(), cache.len());
+	let reduction = expr.reduce_cached(config, loaded, exprs).unwrap();
+	assert_eq!(reduction, Reduction { expr: reduction.expr, steps: 0, out_of_fuel: false, stats: None });
+	assert!(loaded.extend_from_bit_lines("0010", exprs).is_err());
+}
@@ -0,0 +1,209 @@
+//! Terms with de Bruijn indices, for comparing with the literature and other tools
+
+use std::fmt;
+
+use hashdb::{LinkArena, TypeStore};
+
+use super::{BindSubTree, BindTree, Expr, LambdaError};
+
+/// Node of a `DeBruijn` term
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub enum DeBruijnNode {
+	/// Variable bound by the `n`th lambda around it, counting from 1
+	Var(usize),
+	/// Lambda, followed by its body
+	Lambda,
+	/// Application, followed by its function and then its argument
+	App,
+}
+
+/// Lambda term using de Bruijn indices, `[x y] x` is `λ λ 2`.
+/// Indices larger than the number of lambdas around them are free variables.
+/// Stored as its nodes in prefix order so deep terms don't need recursion.
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+pub struct DeBruijn {
+	pub(super) nodes: Vec<DeBruijnNode>,
+}
+impl DeBruijn {
+	/// Variable bound by the `index`th lambda around it, indices start at 1 so 0 is an error
+	pub fn var(index: usize) -> Result<Self, LambdaError> {
+		if index == 0 { return Err(LambdaError::ZeroDeBruijnIndex) }
+		Ok(Self { nodes: vec![DeBruijnNode::Var(index)] })
+	}
+	pub fn lambda(body: DeBruijn) -> Self {
+		let mut nodes = Vec::with_capacity(body.nodes.len() + 1);
+		nodes.push(DeBruijnNode::Lambda);
+		nodes.extend(body.nodes);
+		Self { nodes }
+	}
+	pub fn app(func: DeBruijn, args: DeBruijn) -> Self {
+		let mut nodes = Vec::with_capacity(func.nodes.len() + args.nodes.len() + 1);
+		nodes.push(DeBruijnNode::App);
+		nodes.extend(func.nodes);
+		nodes.extend(args.nodes);
+		Self { nodes }
+	}
+	/// Nodes in prefix order
+	pub fn nodes(&self) -> &[DeBruijnNode] {
+		&self.nodes
+	}
+	/// Number of lambdas around each node
+	fn depths(&self) -> Vec<usize> {
+		let mut depths = Vec::with_capacity(self.nodes.len());
+		let mut slots = vec![0];
+		for node in &self.nodes {
+			let depth = slots.pop().unwrap();
+			depths.push(depth);
+			match node {
+				DeBruijnNode::Var(_) => {}
+				DeBruijnNode::Lambda => slots.push(depth + 1),
+				DeBruijnNode::App => slots.extend([depth, depth]),
+			}
+		}
+		depths
+	}
+	/// Convert to an expression, free variables stay free
+	pub fn to_expr<'e>(&self, exprs: &'e impl TypeStore<'e>) -> Result<&'e Expr<'e>, LambdaError> {
+		let binds = &LinkArena::new();
+		let mut built: Vec<(&'e Expr<'e>, &BindSubTree)> = Vec::new();
+		// Children come after their parents, so build from the end
+		for (node, depth) in self.nodes.iter().zip(self.depths()).rev() {
+			let term = match *node {
+				DeBruijnNode::Var(index) if index <= depth => (Expr::VAR, BindSubTree::end(depth + 1 - index, binds)),
+				DeBruijnNode::Var(_) => (Expr::VAR, BindSubTree::NONE),
+				DeBruijnNode::Lambda => {
+					let (body, mut tree) = built.pop().unwrap();
+					let bind = tree.pop_binding(binds, &(depth + 1), exprs)?;
+					(Expr::lambda(bind, body, exprs), tree)
+				}
+				DeBruijnNode::App => {
+					let (func, func_tree) = built.pop().unwrap();
+					let (args, args_tree) = built.pop().unwrap();
+					(Expr::app(func, args, exprs), BindSubTree::branch(func_tree, args_tree, binds))
+				}
+			};
+			built.push(term);
+		}
+		Ok(built.pop().unwrap().0)
+	}
+}
+
+impl fmt::Display for DeBruijn {
+	/// Prints `λ λ 2 (2 1)`, lambdas extend as far right as possible and application is left associative
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		#[derive(PartialEq)]
+		enum Slot {
+			Term,
+			Func,
+			Args,
+			Close,
+		}
+		let mut slots = vec![Slot::Term];
+		for node in &self.nodes {
+			let slot = slots.pop().unwrap();
+			if slot == Slot::Args { f.write_str(" ")? }
+			if matches!((slot, node), (Slot::Func, DeBruijnNode::Lambda) | (Slot::Args, DeBruijnNode::Lambda | DeBruijnNode::App)) {
+				f.write_str("(")?;
+				slots.push(Slot::Close);
+			}
+			match node {
+				DeBruijnNode::Var(index) => write!(f, "{index}")?,
+				DeBruijnNode::Lambda => {
+					f.write_str("λ ")?;
+					slots.push(Slot::Term);
+				}
+				DeBruijnNode::App => slots.extend([Slot::Args, Slot::Func]),
+			}
+			while slots.last() == Some(&Slot::Close) {
+				slots.pop();
+				f.write_str(")")?;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl<'e> Expr<'e> {
+	/// Convert to de Bruijn indices, free variables get the index just past the lambdas around them
+	pub fn to_debruijn(self: &'e Expr<'e>) -> Result<DeBruijn, LambdaError> {
+		let trees = &LinkArena::new();
+		let mut nodes = Vec::new();
+		let mut tasks = vec![(self, BindTree::NONE, 0)];
+		while let Some((expr, mut tree, depth)) = tasks.pop() {
+			nodes.push(match expr {
+				Expr::Variable => match tree {
+					BindTree::End(level) => DeBruijnNode::Var(depth + 1 - level),
+					BindTree::None => DeBruijnNode::Var(depth + 1),
+					BindTree::Branch(..) => return Err(LambdaError::UnexpectedBranchInSubstitution),
+				}
+				Expr::Lambda { bind, expr } => {
+					tree.push_binding(trees, depth + 1, bind)?;
+					tasks.push((expr, tree, depth + 1));
+					DeBruijnNode::Lambda
+				}
+				Expr::Application { func, args } => {
+					let (func_tree, args_tree) = tree.split()?;
+					tasks.push((args, args_tree, depth));
+					tasks.push((func, func_tree, depth));
+					DeBruijnNode::App
+				}
+			});
+		}
+		Ok(DeBruijn { nodes })
+	}
+}
+
+#[test]
+fn debruijn_test() {
+	use crate::{name::NamespaceMut, parse::{parse, parse_debruijn}};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	for (string, debruijn) in [
+		("[x y] x", "λ λ 2"),
+		("[x y z] x z (y z)", "λ λ λ 3 1 (2 1)"),
+		("[f x] f (f x)", "λ λ 2 (2 1)"),
+		("([x] x x) ([x] x x)", "(λ 1 1) (λ 1 1)"),
+		("[x] x ([y] y x) x", "λ 1 (λ 1 2) 1"),
+		("[x] y x", "λ 2 1"),
+	] {
+		let expr = parse(string, namespace, exprs).unwrap();
+		let term = expr.to_debruijn().unwrap();
+		assert_eq!(term.to_string(), debruijn);
+		assert_eq!(parse_debruijn(debruijn).unwrap(), term);
+		assert_eq!(term.to_expr(exprs).unwrap(), expr);
+	}
+	assert_eq!(parse_debruijn("\\ \\ 2 λ 1 2").unwrap().to_string(), "λ λ 2 (λ 1 2)");
+	assert!(parse_debruijn("λ 0").is_err());
+	assert!(matches!(DeBruijn::var(0), Err(LambdaError::ZeroDeBruijnIndex)));
+
+	// Round trips of pseudorandom terms, free variables always get the index just past their lambdas so they convert back the same
+	let mut seed = 0x2545_f491_4f6c_dd1du64;
+	let mut random = |n: usize| {
+		seed ^= seed << 13;
+		seed ^= seed >> 7;
+		seed ^= seed << 17;
+		seed as usize % n
+	};
+	for _ in 0..500 {
+		let mut nodes = Vec::new();
+		let mut slots = vec![0];
+		while let Some(depth) = slots.pop() {
+			let node = match random(if nodes.len() + slots.len() > 60 { 1 } else { 3 }) {
+				0 => DeBruijnNode::Var(random(depth + 1) + 1),
+				1 => DeBruijnNode::Lambda,
+				_ => DeBruijnNode::App,
+			};
+			match node {
+				DeBruijnNode::Var(_) => {}
+				DeBruijnNode::Lambda => slots.push(depth + 1),
+				DeBruijnNode::App => slots.extend([depth, depth]),
+			}
+			nodes.push(node);
+		}
+		let term = DeBruijn { nodes };
+		let expr = term.to_expr(exprs).unwrap();
+		assert_eq!(expr.to_debruijn().unwrap(), term);
+		assert_eq!(parse_debruijn(&term.to_string()).unwrap(), term);
+	}
+}
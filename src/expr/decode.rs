@@ -0,0 +1,225 @@
+//! Recognizes church and Scott encoded data in normal forms, so results can be shown as `5`, `true` or `[1,2,3]`
+
+use std::{fmt, mem};
+
+use hashdb::LinkArena;
+
+use super::{BindSubTree, BindTree, Expr};
+
+/// Data recognized in an expression
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Decoded<'e> {
+	Nat(u64),
+	Bool(bool),
+	/// Numeral that is a printable ascii character
+	Char(char),
+	Pair(Box<Decoded<'e>>, Box<Decoded<'e>>),
+	List(Vec<Decoded<'e>>),
+	/// List of characters
+	String(String),
+	/// Part of a pair or list that isn't recognized
+	Expr(&'e Expr<'e>),
+}
+impl<'e> fmt::Display for Decoded<'e> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Decoded::Nat(n) => write!(f, "{n}"),
+			Decoded::Bool(b) => write!(f, "{b}"),
+			Decoded::Char(c) => write!(f, "{c:?}"),
+			Decoded::Pair(first, second) => write!(f, "({first}, {second})"),
+			Decoded::List(items) => {
+				f.write_str("[")?;
+				for (i, item) in items.iter().enumerate() {
+					if i != 0 { f.write_str(",")? }
+					write!(f, "{item}")?;
+				}
+				f.write_str("]")
+			}
+			Decoded::String(string) => write!(f, "{string:?}"),
+			Decoded::Expr(expr) => write!(f, "{expr}"),
+		}
+	}
+}
+
+/// Expression together with the binding tree of the lambdas around it
+type Scoped<'e, 't> = (&'e Expr<'e>, &'t BindSubTree<'t>);
+
+/// Body of lambda, whose variable is bound at `level`
+fn lambda<'e, 't>((expr, mut tree): Scoped<'e, 't>, level: usize, trees: &'t LinkArena<'t>) -> Option<Scoped<'e, 't>> {
+	match expr {
+		Expr::Lambda { bind, expr } => {
+			tree.push_binding(trees, level, bind).ok()?;
+			Some((expr, tree))
+		}
+		_ => None,
+	}
+}
+fn app<'e, 't>((expr, tree): Scoped<'e, 't>) -> Option<(Scoped<'e, 't>, Scoped<'e, 't>)> {
+	match expr {
+		Expr::Application { func, args } => {
+			let (func_tree, args_tree) = tree.split().ok()?;
+			Some(((func, func_tree), (args, args_tree)))
+		}
+		_ => None,
+	}
+}
+/// Level of the lambda binding the variable
+fn var((expr, tree): Scoped) -> Option<usize> {
+	match (expr, tree) {
+		(Expr::Variable, BindTree::End(level)) => Some(*level),
+		_ => None,
+	}
+}
+/// Subexpression that doesn't use any of the lambdas around it
+fn closed<'e>((expr, tree): Scoped<'e, '_>) -> Option<&'e Expr<'e>> {
+	matches!(tree, BindTree::None).then_some(expr)
+}
+
+/// Church numeral in the form `[f x] f (f .. x)` beta reduction produces from other numerals in that form.
+/// One eta reduces to `[f] f`, which isn't read as a numeral as it is the identity function.
+pub(super) fn canonical_nat(expr: &Expr) -> Option<u64> {
+	let trees = &LinkArena::new();
+	let mut body = lambda(lambda((expr, BindTree::NONE), 1, trees)?, 2, trees)?;
+	let mut n = 0;
+	while var(body) != Some(2) {
+		let (func, args) = app(body)?;
+		if var(func) != Some(1) { return None }
+		n += 1;
+		body = args;
+	}
+	Some(n)
+}
+/// Scott numeral, zero is `[z s] z` and successor of `n` is `[z s] s n`
+fn scott_nat<'e>(mut expr: &'e Expr<'e>) -> Option<u64> {
+	let trees = &LinkArena::new();
+	let mut n = 0;
+	loop {
+		let body = lambda(lambda((expr, BindTree::NONE), 1, trees)?, 2, trees)?;
+		if var(body) == Some(1) { return Some(n) }
+		let (func, pred) = app(body)?;
+		if var(func) != Some(2) { return None }
+		n += 1;
+		expr = closed(pred)?;
+	}
+}
+/// Church boolean `[x y] x` or `[x y] y`
+pub(super) fn church_bool(expr: &Expr) -> Option<bool> {
+	let trees = &LinkArena::new();
+	match var(lambda(lambda((expr, BindTree::NONE), 1, trees)?, 2, trees)?) {
+		Some(1) => Some(true),
+		Some(2) => Some(false),
+		_ => None,
+	}
+}
+/// Pair `[p] p a b`
+pub(super) fn pair<'e>(expr: &'e Expr<'e>) -> Option<(&'e Expr<'e>, &'e Expr<'e>)> {
+	let trees = &LinkArena::new();
+	let (func, second) = app(lambda((expr, BindTree::NONE), 1, trees)?)?;
+	let (p, first) = app(func)?;
+	if var(p) != Some(1) { return None }
+	Some((closed(first)?, closed(second)?))
+}
+/// Church list (right fold) `[c n] c a (c b .. n)`
+fn church_list<'e>(expr: &'e Expr<'e>) -> Option<Vec<&'e Expr<'e>>> {
+	let trees = &LinkArena::new();
+	let mut body = lambda(lambda((expr, BindTree::NONE), 1, trees)?, 2, trees)?;
+	let mut items = Vec::new();
+	while var(body) != Some(2) {
+		let (func, tail) = app(body)?;
+		let (c, item) = app(func)?;
+		if var(c) != Some(1) { return None }
+		items.push(closed(item)?);
+		body = tail;
+	}
+	Some(items)
+}
+/// Scott list, nil is `[n c] n` and cons is `[n c] c head tail`
+fn scott_list<'e>(mut expr: &'e Expr<'e>) -> Option<Vec<&'e Expr<'e>>> {
+	let trees = &LinkArena::new();
+	let mut items = Vec::new();
+	loop {
+		let body = lambda(lambda((expr, BindTree::NONE), 1, trees)?, 2, trees)?;
+		if var(body) == Some(1) { return Some(items) }
+		let (func, tail) = app(body)?;
+		let (c, item) = app(func)?;
+		if var(c) != Some(2) { return None }
+		items.push(closed(item)?);
+		expr = closed(tail)?;
+	}
+}
+
+/// Lists whose items can all be read the same way, such as all numbers or all characters, otherwise the first reading of every item
+fn lists<'e>(items: Vec<&'e Expr<'e>>, decoded: &mut Vec<Decoded<'e>>) {
+	let readings: Vec<Vec<Decoded>> = items.iter().map(|item| item.decode()).collect();
+	let mut found = false;
+	for kind in readings.first().into_iter().flatten().map(mem::discriminant) {
+		let Some(list) = readings.iter().map(|item| item.iter().find(|reading| mem::discriminant(*reading) == kind).cloned()).collect::<Option<Vec<_>>>() else { continue };
+		found = true;
+		decoded.push(match list.iter().map(|item| match item { Decoded::Char(c) => Some(*c), _ => None }).collect::<Option<String>>() {
+			Some(string) => Decoded::String(string),
+			None => Decoded::List(list),
+		});
+	}
+	if !found {
+		decoded.push(Decoded::List(readings.into_iter().zip(items).map(|(item, expr)| item.into_iter().next().unwrap_or(Decoded::Expr(expr))).collect()));
+	}
+}
+
+impl<'e> Expr<'e> {
+	/// All the ways expression can be read as data, most likely first.
+	/// Encodings overlap (`[x y] y` is zero, false and the empty list), so there can be several.
+	pub fn decode(self: &'e Expr<'e>) -> Vec<Decoded<'e>> {
+		let mut decoded = Vec::new();
+		if let Some(n) = canonical_nat(self).or_else(|| scott_nat(self)) {
+			decoded.push(Decoded::Nat(n));
+			if let Some(c) = char::from_u32(n as u32).filter(|c| n < 128 && (c.is_ascii_graphic() || *c == ' ')) {
+				decoded.push(Decoded::Char(c));
+			}
+		}
+		if let Some(b) = church_bool(self) {
+			decoded.push(Decoded::Bool(b));
+		}
+		if let Some(items) = church_list(self) {
+			lists(items, &mut decoded);
+		}
+		if let Some(items) = scott_list(self) {
+			lists(items, &mut decoded);
+		}
+		if let Some((first, second)) = pair(self) {
+			let read = |expr: &'e Expr<'e>| Box::new(expr.decode().into_iter().next().unwrap_or(Decoded::Expr(expr)));
+			decoded.push(Decoded::Pair(read(first), read(second)));
+		}
+		let mut unique = Vec::new();
+		for reading in decoded {
+			if !unique.contains(&reading) { unique.push(reading) }
+		}
+		unique
+	}
+	/// Readings of `decode` separated by ` | `, if there are any
+	pub fn decode_string(self: &'e Expr<'e>) -> Option<String> {
+		let decoded = self.decode();
+		(!decoded.is_empty()).then(|| decoded.iter().map(ToString::to_string).collect::<Vec<_>>().join(" | "))
+	}
+}
+
+#[test]
+fn decode_test() {
+	use crate::{name::church_namespace, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &church_namespace(exprs);
+	let decoded = |string| parse(string, namespace, exprs).unwrap().reduce(exprs).unwrap().decode_string();
+
+	assert_eq!(decoded("5").as_deref(), Some("5"));
+	assert_eq!(decoded("[f x] f x").as_deref(), Some("1"));
+	assert_eq!(decoded("[f] f"), None);
+	assert_eq!(decoded("65").as_deref(), Some("65 | 'A'"));
+	assert_eq!(decoded("[x y] x").as_deref(), Some("0 | true | []"));
+	assert_eq!(decoded("[x y] y").as_deref(), Some("0 | false | []"));
+	assert_eq!(decoded("[z s] s ([z s] s ([z s] z))").as_deref(), Some("2"));
+	assert_eq!(decoded("cons 1 (cons 2 (cons 3 nil))").as_deref(), Some("[1,2,3]"));
+	assert_eq!(decoded("scons ([x y] x) (scons ([x y] y) snil)").as_deref(), Some("[0,0] | [true,false] | [[],[]]"));
+	assert_eq!(decoded("cons 72 (cons 105 nil)").as_deref(), Some("[72,105] | \"Hi\""));
+	assert_eq!(decoded("pair 3 (pair ([x y] x) ([x] x x))").as_deref(), Some("(3, (0, (λ1[(1,1)] (x x))))"));
+	assert_eq!(decoded("[x] x x"), None);
+}
@@ -0,0 +1,130 @@
+//! Exports expressions as Graphviz DOT graphs, showing which subterms are shared
+
+use std::{collections::HashMap, fmt::Write};
+
+use super::{Binding, ByAddress, Expr};
+
+/// Node ids by address, subterms and binding trees are hash-consed so equal ones get a single node
+#[derive(Default)]
+struct Ids<'e> {
+	exprs: HashMap<ByAddress<'e, Expr<'e>>, usize>,
+	binds: HashMap<ByAddress<'e, Binding<'e>>, usize>,
+}
+
+/// Leaves can come from constants instead of the arena, so they are keyed by these
+static VARIABLE: Expr<'static> = Expr::Variable;
+static NONE: Binding<'static> = Binding::None;
+static END: Binding<'static> = Binding::End;
+
+fn expr_key<'e>(expr: &'e Expr<'e>) -> ByAddress<'e, Expr<'e>> {
+	ByAddress(if let Expr::Variable = expr { &VARIABLE } else { expr })
+}
+fn binding_key<'e>(bind: &'e Binding<'e>) -> ByAddress<'e, Binding<'e>> {
+	ByAddress(match bind {
+		Binding::None => &NONE,
+		Binding::End => &END,
+		Binding::Branch(..) => bind,
+	})
+}
+
+impl<'e> Expr<'e> {
+	/// DOT graph of the expression named `name`, with a node for every distinct subterm.
+	/// If `bindings` is set the binding tree of every lambda is drawn with dashed edges, branching like the applications in its body down to the variables it binds.
+	pub fn to_dot(self: &'e Expr<'e>, name: &str, bindings: bool) -> String {
+		let mut ids = Ids::default();
+		let mut dot = String::new();
+		writeln!(dot, "digraph {name:?} {{").unwrap();
+		writeln!(dot, "\tlabel = {name:?};").unwrap();
+		writeln!(dot, "\tnode [fontname = \"monospace\"];").unwrap();
+
+		let mut tasks = vec![self];
+		ids.exprs.insert(expr_key(self), 0);
+		while let Some(expr) = tasks.pop() {
+			let id = ids.exprs[&expr_key(expr)];
+			let mut edge = |dot: &mut String, child: &'e Expr<'e>, label: &str| {
+				let len = ids.exprs.len();
+				let child_id = *ids.exprs.entry(expr_key(child)).or_insert_with(|| {
+					tasks.push(child);
+					len
+				});
+				writeln!(dot, "\te{id} -> e{child_id} [label = \"{label}\"];").unwrap();
+			};
+			match expr {
+				Expr::Variable => writeln!(dot, "\te{id} [label = \"x\"];").unwrap(),
+				Expr::Lambda { bind, expr: body } => {
+					writeln!(dot, "\te{id} [label = \"λ\"];").unwrap();
+					edge(&mut dot, body, "body");
+					if bindings && *bind != Binding::NONE {
+						let bind_id = binding_dot(&mut dot, bind, &mut ids.binds);
+						writeln!(dot, "\te{id} -> b{bind_id} [label = \"bind\", style = dashed];").unwrap();
+					}
+				}
+				Expr::Application { func, args } => {
+					writeln!(dot, "\te{id} [label = \"@\"];").unwrap();
+					edge(&mut dot, func, "func");
+					edge(&mut dot, args, "args");
+				}
+			}
+		}
+		dot.push_str("}\n");
+		dot
+	}
+}
+
+/// Add nodes of binding tree that aren't in the graph yet, returns the id of its root
+fn binding_dot<'e>(dot: &mut String, bind: &'e Binding<'e>, ids: &mut HashMap<ByAddress<'e, Binding<'e>>, usize>) -> usize {
+	if let Some(id) = ids.get(&binding_key(bind)) { return *id }
+	let root = ids.len();
+	ids.insert(binding_key(bind), root);
+	let mut tasks = vec![bind];
+	while let Some(bind) = tasks.pop() {
+		let id = ids[&binding_key(bind)];
+		match bind {
+			Binding::None => writeln!(dot, "\tb{id} [label = \"none\", shape = point];").unwrap(),
+			Binding::End => writeln!(dot, "\tb{id} [label = \"var\", shape = box, style = dashed];").unwrap(),
+			Binding::Branch(func, args) => {
+				writeln!(dot, "\tb{id} [label = \"\", shape = circle, style = dashed];").unwrap();
+				for (child, label) in [(*func, "func"), (*args, "args")] {
+					if child == Binding::NONE { continue }
+					let len = ids.len();
+					let child_id = *ids.entry(binding_key(child)).or_insert_with(|| {
+						tasks.push(child);
+						len
+					});
+					writeln!(dot, "\tb{id} -> b{child_id} [label = \"{label}\", style = dashed];").unwrap();
+				}
+			}
+		}
+	}
+	root
+}
+
+#[test]
+fn dot_test() {
+	use hashdb::{LinkArena, TypeStore};
+	use crate::{name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let expr = parse("[x] (x x) (x x)", namespace, exprs).unwrap();
+
+	// `x x` is shared, and so is the variable
+	let dot = expr.to_dot("double", false);
+	assert!(dot.starts_with("digraph \"double\" {\n"));
+	assert!(dot.ends_with("}\n"));
+	assert_eq!(dot.matches("[label = \"@\"]").count(), 2);
+	assert_eq!(dot.matches("[label = \"x\"]").count(), 1);
+	assert_eq!(dot.matches("-> e").count(), 5);
+	assert!(!dot.contains("bind"));
+
+	// Binding tree `((.,.),(.,.))` shares its two halves
+	let dot = expr.to_dot("double", true);
+	assert_eq!(dot.matches("[label = \"bind\", style = dashed]").count(), 1);
+	assert_eq!(dot.matches("shape = circle").count(), 2);
+	assert_eq!(dot.matches("shape = box").count(), 1);
+	assert_eq!(dot.matches("-> b").count(), 5);
+
+	// Variables are a single node even if they don't share an address
+	let dot = Expr::app(Expr::VAR, exprs.add(Expr::Variable), exprs).to_dot("free", false);
+	assert_eq!(dot.matches("[label = \"x\"]").count(), 1);
+}
@@ -0,0 +1,173 @@
+//! Checks whether two expressions are beta equivalent, giving up once a budget is used up
+
+use hashdb::{LinkArena, TypeStore};
+
+use super::{BindIndex, BindSubTree, BindTree, ByAddress, Expr, LambdaError, ReduceConfig, ReduceState, ReductionStrategy};
+
+/// Outcome of `Expr::beta_eq`
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub enum Equivalence {
+	/// Both expressions reduce to the same term
+	Equal,
+	/// Expressions have different normal forms or Böhm trees
+	NotEqual,
+	/// Budget ran out before a difference or a common normal form was found
+	Unknown,
+}
+
+/// Whether expressions are the same, by address as they are hash-consed instead of comparing them node by node.
+/// Variables may be constants that aren't in the arena, so they are compared by value.
+fn same_expr(left: &Expr, right: &Expr) -> bool {
+	match (left, right) {
+		(Expr::Variable, Expr::Variable) => true,
+		_ => ByAddress(left) == ByAddress(right),
+	}
+}
+/// Whether expressions are in the same place with the same variables bound, comparing binding trees like `same_expr`
+fn same_index(left: &BindIndex, right: &BindIndex) -> bool {
+	left.index == right.index && match (left.tree, right.tree) {
+		(BindTree::Branch(..), BindTree::Branch(..)) => ByAddress(left.tree) == ByAddress(right.tree),
+		(left, right) => left == right,
+	}
+}
+
+/// Node of a Böhm tree `[x y ..] v a b ..` with the arguments left to compare
+struct BohmNode<'e, 'r> {
+	lambdas: usize,
+	/// Level of the lambda binding the head variable, None if it is free
+	head: Option<usize>,
+	args: Vec<(&'e Expr<'e>, BindIndex<'r>)>,
+}
+
+/// Reduce to head normal form and split into lambdas, head variable and arguments. None if no head normal form was found.
+fn bohm_node<'e, 'r>(
+	expr: &'e Expr<'e>,
+	mut bind_index: BindIndex<'r>,
+	state: &mut ReduceState<'_, 'e>,
+	reps: &'r impl TypeStore<'r>,
+	exprs: &'e impl TypeStore<'e>,
+) -> Result<Option<BohmNode<'e, 'r>>, LambdaError> {
+	let depth = bind_index.index;
+	let mut expr = match expr.partial_reduce(&mut bind_index, ReductionStrategy::HeadNormalForm, state, reps, exprs) {
+		Ok(_) if state.out_of_fuel => return Ok(None),
+		Ok(expr) => expr,
+		Err(LambdaError::Diverges(_) | LambdaError::SizeLimitExceeded(_) | LambdaError::RecursionDepthExceeded) => return Ok(None),
+		Err(err) => return Err(err),
+	};
+	while let Expr::Lambda { bind, expr: body } = expr {
+		bind_index.push_binding(bind, reps)?;
+		expr = body;
+	}
+	let mut args = Vec::new();
+	while let Expr::Application { func, args: arg } = expr {
+		let (func_index, args_index) = bind_index.split()?;
+		args.push((*arg, args_index));
+		bind_index = func_index;
+		expr = func;
+	}
+	args.reverse();
+	let head = match (expr, bind_index.tree) {
+		(Expr::Variable, BindTree::End(level)) => Some(*level),
+		(Expr::Variable, BindTree::None) => None,
+		_ => return Err(LambdaError::UnexpectedBranchInSubstitution),
+	};
+	Ok(Some(BohmNode { lambdas: bind_index.index - depth, head, args }))
+}
+
+/// Eta expand node `[x ..] v a ..` to `[x .. y ..] v a .. y ..` with `lambdas` lambdas
+fn eta_pad<'e, 'r>(node: &mut BohmNode<'e, 'r>, depth: usize, lambdas: usize, reps: &'r impl TypeStore<'r>) {
+	let index = depth + lambdas;
+	for (_, bind_index) in node.args.iter_mut() {
+		bind_index.index = index;
+	}
+	node.args.extend((depth + node.lambdas + 1..=index).map(|level| (Expr::VAR, BindIndex::new(index, BindSubTree::end(level, reps)))));
+	node.lambdas = lambdas;
+}
+
+impl<'e> Expr<'e> {
+	/// Check whether expressions denote the same term.
+	/// Normal forms are compared first, reduced in normal order whatever `config.strategy` is (and up to eta conversion if `config.eta` is set).
+	/// If either has no normal form or it can't be found with the fuel, their Böhm trees are compared instead, up to `depth` levels deep and with `config.fuel` for all of the head reductions together.
+	pub fn beta_eq(self: &'e Expr<'e>, other: &'e Expr<'e>, config: &ReduceConfig, depth: usize, exprs: &'e impl TypeStore<'e>) -> Equivalence {
+		if same_expr(self, other) { return Equivalence::Equal }
+		// Other strategies stop before the normal form or loop on terms that have one
+		let normal_order = &ReduceConfig { strategy: ReductionStrategy::NormalOrder, ..config.clone() };
+		let normal_form = |expr: &'e Expr<'e>| match expr.reduce_with(normal_order, exprs) {
+			Ok(reduction) if !reduction.out_of_fuel => Ok(Some(reduction.expr)),
+			Ok(_) => Ok(None),
+			Err(err) => Err(err),
+		};
+		match (normal_form(self), normal_form(other)) {
+			(Ok(Some(left)), Ok(Some(right))) => return if same_expr(left, right) { Equivalence::Equal } else { Equivalence::NotEqual },
+			// Normal order only loops if there is no normal form
+			(Ok(Some(_)), Err(LambdaError::Diverges(_))) | (Err(LambdaError::Diverges(_)), Ok(Some(_))) => return Equivalence::NotEqual,
+			_ => {}
+		}
+		self.bohm_eq(other, config, depth, exprs).unwrap_or(Equivalence::Unknown)
+	}
+
+	/// Compare Böhm trees up to `depth`, any difference found means the expressions aren't equivalent
+	fn bohm_eq(self: &'e Expr<'e>, other: &'e Expr<'e>, config: &ReduceConfig, depth: usize, exprs: &'e impl TypeStore<'e>) -> Result<Equivalence, LambdaError> {
+		let reps = &LinkArena::new();
+		let config = &ReduceConfig { strategy: ReductionStrategy::HeadNormalForm, ..config.clone() };
+		let state = &mut ReduceState::new(config, None, None);
+		let mut complete = true;
+		let mut pairs = vec![((self, BindIndex::DEFAULT), (other, BindIndex::DEFAULT), depth)];
+		while let Some(((left, left_index), (right, right_index), depth)) = pairs.pop() {
+			if same_expr(left, right) && same_index(&left_index, &right_index) { continue }
+			let lambda_depth = left_index.index;
+			let (Some(mut left), Some(mut right)) = (bohm_node(left, left_index, state, reps, exprs)?, bohm_node(right, right_index, state, reps, exprs)?) else {
+				if state.out_of_fuel { return Ok(Equivalence::Unknown) }
+				complete = false;
+				continue
+			};
+			if config.eta && left.lambdas < right.lambdas {
+				eta_pad(&mut left, lambda_depth, right.lambdas, reps);
+			} else if config.eta && right.lambdas < left.lambdas {
+				eta_pad(&mut right, lambda_depth, left.lambdas, reps);
+			}
+			if left.lambdas != right.lambdas || left.head != right.head || left.args.len() != right.args.len() {
+				return Ok(Equivalence::NotEqual);
+			}
+			if depth == 0 {
+				complete &= left.args.is_empty();
+				continue
+			}
+			pairs.extend(left.args.into_iter().zip(right.args).rev().map(|(left, right)| (left, right, depth - 1)));
+		}
+		Ok(if complete { Equivalence::Equal } else { Equivalence::Unknown })
+	}
+}
+
+#[test]
+fn beta_eq_test() {
+	use crate::{name::church_namespace, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &church_namespace(exprs);
+	let config = &ReduceConfig { fuel: Some(1_000), ..Default::default() };
+	let eta = &ReduceConfig { eta: true, ..config.clone() };
+	let beta_eq = |left, right, config| parse(left, namespace, exprs).unwrap().beta_eq(parse(right, namespace, exprs).unwrap(), config, 20, exprs);
+
+	// Normal forms
+	assert_eq!(beta_eq("([x y] x) ([x y] y) ([x y] x)", "[x y] y", config), Equivalence::Equal);
+	assert_eq!(beta_eq("[x y] x", "[x y] y", config), Equivalence::NotEqual);
+	assert_eq!(beta_eq("[f] f", "[f x] f x", config), Equivalence::NotEqual);
+	assert_eq!(beta_eq("[f] f", "[f x] f x", eta), Equivalence::Equal);
+	assert_eq!(beta_eq("[x] x", "omega", config), Equivalence::NotEqual);
+	// Normal forms are found in normal order whatever the strategy is
+	let whnf = &ReduceConfig { strategy: ReductionStrategy::WeakHeadNormalForm, ..config.clone() };
+	let cbv = &ReduceConfig { strategy: ReductionStrategy::CallByValue, ..config.clone() };
+	assert_eq!(beta_eq("[x] ([y] y) x", "[x] x", whnf), Equivalence::Equal);
+	assert_eq!(beta_eq("([x y] y) omega", "[y] y", cbv), Equivalence::Equal);
+
+	// Böhm trees of terms without normal forms
+	// Subtrees reach the same expression, so the infinite trees are known to be equal
+	assert_eq!(beta_eq("Y succ", "succ (Y succ)", config), Equivalence::Equal);
+	assert_eq!(beta_eq("[a] Y ([r x] x r)", "[a] Y ([r x] x x)", config), Equivalence::NotEqual);
+	assert_eq!(beta_eq("[g] Y ([r] g r)", "[g] Y ([r] g (g r))", config), Equivalence::Unknown);
+	assert_eq!(beta_eq("[g] Y ([r] g r)", "[g] Y ([r] g (g r g))", config), Equivalence::NotEqual);
+	assert_eq!(beta_eq("[f] Y f", "[f] Z f", config), Equivalence::NotEqual);
+	assert_eq!(beta_eq("omega", "([x] x x x) ([x] x x x)", config), Equivalence::Unknown);
+	assert_eq!(beta_eq("[x] x omega", "[x] x ([y] y)", config), Equivalence::NotEqual);
+}
@@ -0,0 +1,99 @@
+//! This file contains functions to eta reduce and eta expand expressions
+
+use hashdb::{LinkArena, TypeStore};
+
+use super::{BindIndex, Binding, Expr, LambdaError};
+
+impl<'e> Expr<'e> {
+	/// Eta reduce bottom-up so that `[x y] f x y` becomes `f`, using a stack of tasks instead of recursion so deep expressions don't overflow
+	fn recur_eta_reduce<'r>(
+		self: &'e Expr<'e>,
+		bind_index: &mut BindIndex<'r>,
+		reps: &'r impl TypeStore<'r>,
+		exprs: &'e impl TypeStore<'e>,
+	) -> Result<&'e Expr<'e>, LambdaError> {
+		enum Task<'e, 'r> {
+			Reduce(&'e Expr<'e>, BindIndex<'r>),
+			Lambda,
+			Application,
+		}
+		let mut tasks = vec![Task::Reduce(self, bind_index.clone())];
+		let mut reduced: Vec<(&'e Expr<'e>, BindIndex<'r>)> = Vec::new();
+		while let Some(task) = tasks.pop() {
+			match task {
+				Task::Reduce(expr @ Expr::Variable, bind_index) => reduced.push((expr, bind_index)),
+				Task::Reduce(Expr::Lambda { bind, expr }, mut bind_index) => {
+					bind_index.push_binding(bind, reps)?;
+					tasks.push(Task::Lambda);
+					tasks.push(Task::Reduce(expr, bind_index));
+				}
+				Task::Reduce(Expr::Application { func, args }, bind_index) => {
+					let (func_bind, args_bind) = bind_index.split()?;
+					tasks.push(Task::Application);
+					tasks.push(Task::Reduce(args, args_bind));
+					tasks.push(Task::Reduce(func, func_bind));
+				}
+				Task::Lambda => {
+					let (reduced_expr, mut bind_index) = reduced.pop().unwrap();
+					let bind = bind_index.pop_binding(reps, exprs)?;
+					reduced.push(match (bind, reduced_expr) {
+						// `[x] f x` where x is only used as the argument, bind_index now only has to cover `f`
+						(Binding::Branch(Binding::None, Binding::End), Expr::Application { func, args: Expr::Variable }) => {
+							let (func_bind, _) = bind_index.split()?;
+							(*func, func_bind)
+						}
+						_ => (Expr::lambda(bind, reduced_expr, exprs), bind_index),
+					});
+				}
+				Task::Application => {
+					let (args, args_bind) = reduced.pop().unwrap();
+					let (func, func_bind) = reduced.pop().unwrap();
+					reduced.push((Expr::app(func, args, exprs), BindIndex::join(func_bind, args_bind, reps)));
+				}
+			}
+		}
+		let (expr, reduced_bind) = reduced.pop().unwrap();
+		*bind_index = reduced_bind;
+		Ok(expr)
+	}
+
+	/// Eta reduce all lambdas of the form `[x] f x` (where `x` is not used in `f`) to `f`
+	pub fn eta_reduce(self: &'e Expr<'e>, exprs: &'e impl TypeStore<'e>) -> Result<&'e Expr<'e>, LambdaError> {
+		let reps = &LinkArena::new();
+		self.recur_eta_reduce(&mut BindIndex::DEFAULT.clone(), reps, exprs)
+	}
+
+	/// Eta expand expression `f` to `[x] f x`
+	pub fn eta_expand(self: &'e Expr<'e>, exprs: &'e impl TypeStore<'e>) -> &'e Expr<'e> {
+		Expr::lambda(Binding::right(Binding::END, exprs), Expr::app(self, Expr::VAR, exprs), exprs)
+	}
+}
+
+#[test]
+fn eta_test() {
+	use crate::{expr::ReduceConfig, name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let parsed = |string| parse(string, namespace, exprs).unwrap();
+
+	assert_eq!(parsed("[x] f x").eta_reduce(exprs).unwrap(), Expr::VAR);
+	assert_eq!(parsed("[x y] z x y").eta_reduce(exprs).unwrap(), Expr::VAR);
+	assert_eq!(parsed("[x y] x z y").eta_reduce(exprs).unwrap(), parsed("[x] x z"));
+	assert_eq!(parsed("[f x] f x").eta_reduce(exprs).unwrap(), parsed("[f] f"));
+	assert_eq!(parsed("[x] x x").eta_reduce(exprs).unwrap(), parsed("[x] x x"));
+	assert_eq!(parsed("[x] ([y] y x) x").eta_reduce(exprs).unwrap(), parsed("[x] ([y] y x) x"));
+
+	let id = parsed("[x] x");
+	assert_eq!(id.eta_expand(exprs), parsed("[y] ([x] x) y"));
+	assert_eq!(id.eta_expand(exprs).eta_reduce(exprs).unwrap(), id);
+	// Nested far deeper than the native stack would allow with recursion
+	let deep = (0..50_000).fold(Expr::VAR, |expr, _| expr.eta_expand(exprs));
+	assert_eq!(deep.eta_reduce(exprs).unwrap(), Expr::VAR);
+
+	// `[x] f x` and `f` only have the same normal form with eta enabled
+	let config = &ReduceConfig { eta: true, ..Default::default() };
+	let expr = parsed("[f] [x] ([y] f y) x");
+	assert_eq!(expr.reduce(exprs).unwrap(), parsed("[f x] f x"));
+	assert_eq!(expr.reduce_with(config, exprs).unwrap().expr, parsed("[f] f"));
+}
@@ -0,0 +1,197 @@
+//! Jets: arithmetic on church numerals computed natively instead of by beta reduction
+
+use std::collections::HashMap;
+
+use hashdb::{LinkArena, TypeStore};
+
+use super::{Binding, ByAddress, Expr};
+
+/// Arithmetic function that can be computed natively, recognized by its canonical definition
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub enum Jet {
+	Add,
+	Mul,
+	Pow,
+	Pred,
+	Sub,
+	Leq,
+	Eq,
+}
+impl Jet {
+	pub const ALL: [Jet; 7] = [Self::Add, Self::Mul, Self::Pow, Self::Pred, Self::Sub, Self::Leq, Self::Eq];
+	/// Name it is usually defined as
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Add => "add",
+			Self::Mul => "mul",
+			Self::Pow => "pow",
+			Self::Pred => "pred",
+			Self::Sub => "sub",
+			Self::Leq => "leq",
+			Self::Eq => "eq",
+		}
+	}
+	/// Canonical definition, a namespace entry with the same normal form is jetted
+	pub fn definition(self) -> &'static str {
+		match self {
+			Self::Add => "[m n f x] m f (n f x)",
+			Self::Mul => "[m n f] m (n f)",
+			Self::Pow => "[m n] n m",
+			Self::Pred => "[n f x] n ([g h] h (g f)) ([u] x) ([u] u)",
+			Self::Sub => "[m n] n ([n f x] n ([g h] h (g f)) ([u] x) ([u] u)) m",
+			Self::Leq => "[m n] (n ([n f x] n ([g h] h (g f)) ([u] x) ([u] u)) m) ([u] [x y] y) ([x y] x)",
+			Self::Eq => "[m n] ([p q] p q p) ((n ([n f x] n ([g h] h (g f)) ([u] x) ([u] u)) m) ([u] [x y] y) ([x y] x)) ((m ([n f x] n ([g h] h (g f)) ([u] x) ([u] u)) n) ([u] [x y] y) ([x y] x))",
+		}
+	}
+	/// Number of numerals it is applied to
+	pub fn arity(self) -> usize {
+		match self {
+			Self::Pred => 1,
+			_ => 2,
+		}
+	}
+	/// Compute result, None if the normal form isn't a numeral or boolean (`pow m 0` is `[x] x`) or it doesn't fit
+	fn compute(self, args: &[u64]) -> Option<Value> {
+		Some(match (self, args) {
+			(Self::Add, &[m, n]) => Value::Nat(m.checked_add(n)?),
+			(Self::Mul, &[m, n]) => Value::Nat(m.checked_mul(n)?),
+			(Self::Pow, &[_, 0]) => return None,
+			(Self::Pow, &[m, n]) => Value::Nat(m.checked_pow(n.try_into().ok()?)?),
+			(Self::Pred, &[n]) => Value::Nat(n.saturating_sub(1)),
+			(Self::Sub, &[m, n]) => Value::Nat(m.saturating_sub(n)),
+			(Self::Leq, &[m, n]) => Value::Bool(m <= n),
+			(Self::Eq, &[m, n]) => Value::Bool(m == n),
+			_ => return None,
+		})
+	}
+	/// Normal form of jet applied to numerals `args`.
+	/// None if it isn't computed natively, results larger than `max_size` (or `MAX_NUMERAL_SIZE`) nodes are left to beta reduction so the size limit is still reported.
+	pub(super) fn evaluate<'e>(self, args: &[u64], max_size: Option<usize>, exprs: &'e impl TypeStore<'e>) -> Option<&'e Expr<'e>> {
+		let max_size = max_size.unwrap_or(MAX_NUMERAL_SIZE).min(MAX_NUMERAL_SIZE) as u64;
+		match self.compute(args)? {
+			Value::Nat(n) if n.saturating_mul(2).saturating_add(3) > max_size => None,
+			Value::Nat(n) => Some(church_numeral(n, exprs)),
+			Value::Bool(b) => Some(church_bool(b, exprs)),
+		}
+	}
+}
+
+/// Largest numeral (in nodes) jets build, so a single jet can't run out of memory when `max_size` isn't set
+const MAX_NUMERAL_SIZE: usize = 1 << 24;
+
+/// Result of a jet
+enum Value {
+	Nat(u64),
+	Bool(bool),
+}
+
+/// Church numeral `[f x] f (f .. x)` in the form beta reduction produces it
+fn church_numeral<'e>(n: u64, exprs: &'e impl TypeStore<'e>) -> &'e Expr<'e> {
+	let (mut body, mut bind_f, mut bind_x) = (Expr::VAR, Binding::NONE, Binding::END);
+	for _ in 0..n {
+		body = Expr::app(Expr::VAR, body, exprs);
+		bind_f = Binding::branch_reduce(Binding::END, bind_f, exprs);
+		bind_x = Binding::branch_reduce(Binding::NONE, bind_x, exprs);
+	}
+	Expr::lambda(bind_f, Expr::lambda(bind_x, body, exprs), exprs)
+}
+
+/// Church boolean `[x y] x` or `[x y] y`
+fn church_bool<'e>(b: bool, exprs: &'e impl TypeStore<'e>) -> &'e Expr<'e> {
+	let (bind_x, bind_y) = if b { (Binding::END, Binding::NONE) } else { (Binding::NONE, Binding::END) };
+	Expr::lambda(bind_x, Expr::lambda(bind_y, Expr::VAR, exprs), exprs)
+}
+
+/// Maps canonical definitions to the jets that compute them, by address so they have to be in the arena expressions are reduced in
+#[derive(Default)]
+pub struct Jets<'e> {
+	map: HashMap<ByAddress<'e, Expr<'e>>, Jet>,
+}
+impl<'e> Jets<'e> {
+	pub fn new() -> Self { Self::default() }
+	/// Registry of all jets, keyed by the normal forms of their canonical definitions
+	pub fn standard(exprs: &'e LinkArena<'e>) -> Self {
+		let namespace = &crate::name::NamespaceMut::new();
+		let mut jets = Self::new();
+		for jet in Jet::ALL {
+			let expr = crate::parse::parse_reduce(jet.definition(), namespace, exprs).expect("jet definitions are valid");
+			jets.insert(expr, jet);
+		}
+		jets
+	}
+	/// Compute `expr` with `jet` whenever it is applied to numerals
+	pub fn insert(&mut self, expr: &'e Expr<'e>, jet: Jet) {
+		self.map.insert(ByAddress(expr), jet);
+	}
+	pub fn get(&self, expr: &'e Expr<'e>) -> Option<Jet> {
+		self.map.get(&ByAddress(expr)).copied()
+	}
+	pub fn len(&self) -> usize { self.map.len() }
+	pub fn is_empty(&self) -> bool { self.map.is_empty() }
+	/// Jet and arguments of `expr`, if it is a jetted function applied to exactly as many arguments as it takes
+	pub(super) fn saturated(&self, expr: &'e Expr<'e>) -> Option<(Jet, Vec<&'e Expr<'e>>)> {
+		let mut head = expr;
+		let mut args = Vec::new();
+		while let Expr::Application { func, args: arg } = head {
+			if args.len() == 2 { return None }
+			args.push(*arg);
+			head = func;
+			if let Some(jet) = Some(head).filter(|head| matches!(head, Expr::Lambda { .. })).and_then(|head| self.get(head)).filter(|jet| jet.arity() == args.len()) {
+				args.reverse();
+				return Some((jet, args));
+			}
+		}
+		None
+	}
+}
+
+#[test]
+fn jets_test() {
+	use crate::{expr::ReduceConfig, name::church_namespace, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &church_namespace(exprs);
+	let jets = &Jets::standard(exprs);
+	assert_eq!(jets.len(), Jet::ALL.len());
+	for jet in Jet::ALL {
+		namespace.add(jet.name(), parse(jet.definition(), namespace, exprs).unwrap().reduce(exprs).unwrap(), exprs);
+	}
+	let config = &ReduceConfig { fuel: Some(1_000_000), ..Default::default() };
+	let cache = &crate::expr::ReduceCache::new();
+
+	// Same normal forms as pure reduction, with fewer beta reductions
+	for jet in Jet::ALL {
+		for m in 0..4 {
+			for n in 0..4 {
+				let string = if jet.arity() == 1 { format!("{} {m}", jet.name()) } else { format!("{} {m} {n}", jet.name()) };
+				let expr = parse(&string, namespace, exprs).unwrap();
+				let jetted = expr.reduce_jetted(config, cache, jets, exprs).unwrap();
+				let pure = expr.reduce_with(config, exprs).unwrap();
+				assert_eq!(jetted.expr, pure.expr, "{string}");
+				assert!(jetted.steps <= pure.steps);
+			}
+		}
+	}
+	// Jets fire inside of larger reductions, arguments are reduced to numerals first
+	let expr = parse("pow (add 1 2) (mul 2 3)", namespace, exprs).unwrap();
+	let jetted = expr.reduce_jetted(config, cache, jets, exprs).unwrap();
+	assert_eq!(jetted.expr, church_numeral(729, exprs));
+	assert_eq!(jetted.expr, expr.reduce_with(config, exprs).unwrap().expr);
+	assert_eq!(jetted.steps, 0);
+	// Subtraction of large numbers is only feasible with jets
+	let expr = parse("leq (pow 10 4) (sub (pow 10 4) 1)", namespace, exprs).unwrap();
+	let jetted = expr.reduce_jetted(config, cache, jets, exprs).unwrap();
+	assert_eq!(jetted.expr, church_bool(false, exprs));
+	// `pow m 0` and `[x] x` are read as one, but aren't in the form beta reduction produces numerals in, so they aren't jetted
+	for string in ["mul (pow 2 0) (pow 2 0)", "pow ([x] x) 3", "sub ([x] x) 0", "add (pow 3 0) 1"] {
+		let expr = parse(string, namespace, exprs).unwrap();
+		assert_eq!(expr.reduce_jetted(config, cache, jets, exprs).unwrap().expr, expr.reduce_with(config, exprs).unwrap().expr, "{string}");
+	}
+	// Beta reductions spent on the arguments of jets that aren't used still count against the fuel
+	let expr = parse("add (pow 3 0) 1", namespace, exprs).unwrap();
+	let config = &ReduceConfig { fuel: Some(15), ..Default::default() };
+	assert!(!expr.reduce_with(config, exprs).unwrap().out_of_fuel);
+	let jetted = expr.reduce_jetted(config, &crate::expr::ReduceCache::new(), jets, exprs).unwrap();
+	assert!(jetted.out_of_fuel);
+	assert_eq!(jetted.steps, 15);
+}
@@ -0,0 +1,319 @@
+//! Krivine machine: call-by-name evaluation of compiled expressions using environments instead of substitution
+
+use hashdb::{LinkArena, TypeStore};
+
+use super::{BindSubTree, BindTree, Expr, LambdaError, ReduceConfig};
+
+/// Instruction of a compiled expression, refers to other instructions by their position in `Code`
+#[derive(Clone, Copy)]
+enum Instr {
+	/// Variable bound by the lambda this many lambdas up (de Bruijn index)
+	Var(usize),
+	/// Variable that isn't bound by any lambda
+	Free,
+	Lambda(usize),
+	App(usize, usize),
+}
+
+/// Expression compiled to de Bruijn indexed instructions
+struct Code {
+	instrs: Vec<Instr>,
+	root: usize,
+}
+impl Code {
+	fn compile(expr: &Expr) -> Result<Self, LambdaError> {
+		enum Task<'e, 't> {
+			Compile(&'e Expr<'e>, &'t BindSubTree<'t>, usize),
+			Lambda,
+			App,
+		}
+		let trees = &LinkArena::new();
+		let mut instrs = Vec::new();
+		let mut compiled = Vec::new();
+		let mut tasks = vec![Task::Compile(expr, BindTree::NONE, 0)];
+		while let Some(task) = tasks.pop() {
+			let instr = match task {
+				Task::Compile(Expr::Variable, tree, depth) => match tree {
+					BindTree::End(level) => Instr::Var(depth - level),
+					BindTree::None => Instr::Free,
+					BindTree::Branch(..) => return Err(LambdaError::UnexpectedBranchInSubstitution),
+				}
+				Task::Compile(Expr::Lambda { bind, expr }, mut tree, depth) => {
+					tree.push_binding(trees, depth + 1, bind)?;
+					tasks.push(Task::Lambda);
+					tasks.push(Task::Compile(expr, tree, depth + 1));
+					continue
+				}
+				Task::Compile(Expr::Application { func, args }, tree, depth) => {
+					let (func_tree, args_tree) = tree.split()?;
+					tasks.push(Task::App);
+					tasks.push(Task::Compile(args, args_tree, depth));
+					tasks.push(Task::Compile(func, func_tree, depth));
+					continue
+				}
+				Task::Lambda => Instr::Lambda(compiled.pop().unwrap()),
+				Task::App => {
+					let args = compiled.pop().unwrap();
+					Instr::App(compiled.pop().unwrap(), args)
+				}
+			};
+			compiled.push(instrs.len());
+			instrs.push(instr);
+		}
+		Ok(Code { instrs, root: compiled.pop().unwrap() })
+	}
+}
+
+/// Instruction paired with the environment its variables are looked up in
+type Closure = (usize, usize);
+
+/// What a variable in an environment refers to
+#[derive(Clone, Copy)]
+enum Entry {
+	Closure(Closure),
+	/// Variable of a lambda whose body is being read back, by level
+	Bound(usize),
+}
+
+/// Expression in weak head normal form
+enum Whnf {
+	/// Lambda with its body
+	Lambda(Closure),
+	/// Variable applied to arguments, the variable is either unbound (`None`) or a lambda being read back, by level
+	Neutral(Option<usize>, Vec<Closure>),
+}
+
+/// Work left for `Machine::read_back`
+enum ReadTask {
+	Eval(Closure),
+	Lambda,
+	Application,
+}
+
+/// Number of environments below which unreachable ones are never collected
+const MIN_COLLECTION: usize = 1 << 16;
+
+struct Machine<'c> {
+	config: &'c ReduceConfig,
+	code: Code,
+	/// Environments as linked lists, every environment is its innermost entry and the environment it extends. Index 0 is the empty environment.
+	/// Entries only refer to older environments.
+	envs: Vec<(Entry, usize)>,
+	/// Number of environments at which unreachable ones are collected next
+	next_collection: usize,
+	steps: usize,
+}
+impl<'c> Machine<'c> {
+	fn extend(&mut self, entry: Entry, env: usize) -> usize {
+		self.envs.push((entry, env));
+		self.envs.len() - 1
+	}
+	fn lookup(&self, mut env: usize, index: usize) -> Entry {
+		for _ in 0..index {
+			env = self.envs[env].1;
+		}
+		self.envs[env].0
+	}
+	/// Drop environments that can't be reached from `roots`, moving the others to the front and updating `roots` to their new positions.
+	/// As entries only refer to older environments, one pass from newest to oldest finds all reachable ones, and moving keeps them in order.
+	fn collect<'r>(&mut self, roots: impl Iterator<Item = &'r mut usize>) {
+		let mut roots = roots.collect::<Vec<_>>();
+		let mut live = vec![false; self.envs.len()];
+		live[0] = true;
+		for root in &roots {
+			live[**root] = true;
+		}
+		for env in (1..self.envs.len()).rev() {
+			if live[env] {
+				let (entry, parent) = self.envs[env];
+				live[parent] = true;
+				if let Entry::Closure((_, closure_env)) = entry {
+					live[closure_env] = true;
+				}
+			}
+		}
+		let mut moved = vec![0; self.envs.len()];
+		let mut len = 0;
+		for env in 0..self.envs.len() {
+			if live[env] {
+				let (mut entry, parent) = self.envs[env];
+				if let Entry::Closure((_, closure_env)) = &mut entry {
+					*closure_env = moved[*closure_env];
+				}
+				self.envs[len] = (entry, moved[parent]);
+				moved[env] = len;
+				len += 1;
+			}
+		}
+		self.envs.truncate(len);
+		for root in &mut roots {
+			**root = moved[**root];
+		}
+		self.next_collection = (2 * len).max(MIN_COLLECTION);
+	}
+	/// Evaluate closure to weak head normal form, the environments of closures in `tasks` are kept when collecting unreachable ones
+	fn run(&mut self, (mut instr, mut env): Closure, tasks: &mut [ReadTask]) -> Result<Whnf, LambdaError> {
+		// Arguments applied to the closure, last is applied first
+		let mut stack: Vec<Closure> = Vec::new();
+		loop {
+			match self.code.instrs[instr] {
+				Instr::App(func, args) => {
+					if stack.len() >= self.config.recursion_limit {
+						return Err(LambdaError::RecursionDepthExceeded);
+					}
+					// Pass on what a variable refers to directly, otherwise arguments that are passed along build up chains of closures
+					stack.push(match self.code.instrs[args] {
+						Instr::Var(index) => match self.lookup(env, index) {
+							Entry::Closure(closure) => closure,
+							Entry::Bound(_) => (args, env),
+						}
+						_ => (args, env),
+					});
+					instr = func;
+				}
+				Instr::Lambda(body) => match stack.pop() {
+					None => return Ok(Whnf::Lambda((body, env))),
+					Some(arg) => {
+						if self.config.fuel.is_some_and(|fuel| self.steps >= fuel) {
+							return Err(LambdaError::OutOfFuel(self.steps));
+						}
+						self.steps += 1;
+						env = self.extend(Entry::Closure(arg), env);
+						instr = body;
+						if self.envs.len() >= self.next_collection {
+							let tasks = tasks.iter_mut().filter_map(|task| match task {
+								ReadTask::Eval((_, env)) => Some(env),
+								_ => None,
+							});
+							self.collect(std::iter::once(&mut env).chain(stack.iter_mut().map(|(_, env)| env)).chain(tasks));
+						}
+					}
+				}
+				Instr::Var(index) => match self.lookup(env, index) {
+					Entry::Closure(closure) => (instr, env) = closure,
+					Entry::Bound(level) => return Ok(Whnf::Neutral(Some(level), stack.into_iter().rev().collect())),
+				}
+				Instr::Free => return Ok(Whnf::Neutral(None, stack.into_iter().rev().collect())),
+			}
+		}
+	}
+	/// Evaluate closure to normal form, evaluating under lambdas and inside of arguments
+	fn read_back<'e>(&mut self, closure: Closure, exprs: &'e impl TypeStore<'e>) -> Result<&'e Expr<'e>, LambdaError> {
+		let binds = &LinkArena::new();
+		let mut level = 0;
+		let mut tasks = vec![ReadTask::Eval(closure)];
+		let mut read: Vec<(&'e Expr<'e>, &BindSubTree)> = Vec::new();
+		while let Some(task) = tasks.pop() {
+			match task {
+				ReadTask::Eval(closure) => match self.run(closure, &mut tasks)? {
+					Whnf::Lambda((body, env)) => {
+						level += 1;
+						let env = self.extend(Entry::Bound(level), env);
+						tasks.push(ReadTask::Lambda);
+						tasks.push(ReadTask::Eval((body, env)));
+					}
+					Whnf::Neutral(head, args) => {
+						read.push(match head {
+							Some(level) => (Expr::VAR, BindSubTree::end(level, binds)),
+							None => (Expr::VAR, BindSubTree::NONE),
+						});
+						for arg in args.into_iter().rev() {
+							tasks.push(ReadTask::Application);
+							tasks.push(ReadTask::Eval(arg));
+						}
+					}
+				}
+				ReadTask::Lambda => {
+					let (body, mut tree) = read.pop().unwrap();
+					let bind = tree.pop_binding(binds, &level, exprs)?;
+					level -= 1;
+					read.push((Expr::lambda(bind, body, exprs), tree));
+				}
+				ReadTask::Application => {
+					let (args, args_tree) = read.pop().unwrap();
+					let (func, func_tree) = read.pop().unwrap();
+					read.push((Expr::app(func, args, exprs), BindSubTree::branch(func_tree, args_tree, binds)));
+				}
+			}
+		}
+		Ok(read.pop().unwrap().0)
+	}
+}
+
+/// Outcome of reducing an expression with `Expr::reduce_krivine`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KrivineReduction<'e> {
+	/// Normal form of the expression
+	pub expr: &'e Expr<'e>,
+	/// Number of beta reductions performed
+	pub steps: usize,
+}
+
+impl<'e> Expr<'e> {
+	/// Reduce expression to normal form by compiling it and running it on a Krivine machine, reading back under lambdas.
+	/// Contracts the same redexes as normal order reduction, but never rebuilds expressions until the normal form is read back.
+	/// Uses `fuel`, `recursion_limit` (as the maximum number of pending arguments) and `eta` from `config`, running out of fuel is an error.
+	pub fn reduce_krivine(self: &'e Expr<'e>, config: &ReduceConfig, exprs: &'e impl TypeStore<'e>) -> Result<KrivineReduction<'e>, LambdaError> {
+		let code = Code::compile(self)?;
+		let root = code.root;
+		// Index 0 is the empty environment, its entry is never looked up
+		let mut machine = Machine { config, code, envs: vec![(Entry::Bound(0), 0)], next_collection: MIN_COLLECTION, steps: 0 };
+		let mut expr = machine.read_back((root, 0), exprs)?;
+		if config.eta {
+			expr = expr.eta_reduce(exprs)?;
+		}
+		Ok(KrivineReduction { expr, steps: machine.steps })
+	}
+}
+
+#[test]
+fn reduce_krivine_test() {
+	use crate::{name::church_namespace, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &church_namespace(exprs);
+	let config = &ReduceConfig { fuel: Some(1_000_000), ..Default::default() };
+
+	// Same normal forms as normal order reduction, found with the same number of beta reductions
+	for string in [
+		"[x] x", "[x y] y ([z] z x)", "free ([x] x) free", "[a] ([x y] y) (([x] x x) ([x] x x)) a",
+		"Y ([r n] iszero n 1 (mul n (r (pred n)))) 4",
+	] {
+		let expr = parse(string, namespace, exprs).unwrap();
+		let krivine = expr.reduce_krivine(config, exprs).unwrap();
+		let substitution = expr.reduce_with(config, exprs).unwrap();
+		assert_eq!((krivine.expr, krivine.steps), (substitution.expr, substitution.steps));
+	}
+
+	let omega = parse("([x] x x) ([x] x x)", namespace, exprs).unwrap();
+	assert!(matches!(omega.reduce_krivine(config, exprs), Err(LambdaError::OutOfFuel(_))));
+
+	// Long enough that unreachable environments are collected on the way
+	let expr = parse("mul 300 301 ([b x y] b y x) ([x y] x)", namespace, exprs).unwrap();
+	let krivine = expr.reduce_krivine(config, exprs).unwrap();
+	assert!(krivine.steps > 2 * MIN_COLLECTION, "{}", krivine.steps);
+	assert_eq!(krivine.expr, parse("[x y] x", namespace, exprs).unwrap());
+}
+
+/// Times `reduce_krivine` against `reduce_with` on church arithmetic, run with `cargo test --release reduce_krivine_bench -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn reduce_krivine_bench() {
+	use std::time::Instant;
+	use crate::{name::church_namespace, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &church_namespace(exprs);
+	let config = &ReduceConfig { fuel: None, ..Default::default() };
+	for string in ["pred (mul 40 60)", "mul 300 301 ([b x y] b y x) ([x y] x)", "Y ([r n] iszero n 1 (mul n (r (pred n)))) 5"] {
+		let expr = parse(string, namespace, exprs).unwrap();
+		let start = Instant::now();
+		let krivine = expr.reduce_krivine(config, exprs).unwrap();
+		let krivine_time = start.elapsed();
+		let start = Instant::now();
+		let substitution = expr.reduce_with(config, exprs).unwrap();
+		let substitution_time = start.elapsed();
+		assert_eq!((krivine.expr, krivine.steps), (substitution.expr, substitution.steps));
+		println!("`{string}`, {} beta reductions: reduce_with took {substitution_time:?}, reduce_krivine took {krivine_time:?}", krivine.steps);
+	}
+}
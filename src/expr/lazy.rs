@@ -0,0 +1,219 @@
+//! Call-by-need reduction: arguments are delayed as thunks on a heap, evaluated at most once and then updated with their value
+
+use hashdb::{LinkArena, TypeStore};
+
+use super::{BindSubTree, BindTree, Binding, Expr, LambdaError, ReduceConfig};
+
+/// Positions of variables in an expression, ends are the heap indices of the thunks they refer to
+type Env<'t> = &'t BindTree<'t, usize>;
+
+/// Expression in weak head normal form
+#[derive(Clone)]
+enum Value<'e, 't> {
+	/// Lambda with the thunks its variables refer to
+	Closure(&'e Binding<'e>, &'e Expr<'e>, Env<'t>),
+	/// Variable applied to arguments, the variable is either unbound (`None`) or a lambda being read back, by level
+	Neutral(Option<usize>, Vec<usize>),
+}
+
+enum Thunk<'e, 't> {
+	/// Argument that hasn't been needed yet
+	Delayed(&'e Expr<'e>, Env<'t>),
+	/// Argument currently being evaluated
+	Evaluating,
+	/// Argument that has been evaluated, reused every time it is needed again
+	Evaluated(Value<'e, 't>),
+	/// Variable of a lambda whose body is being read back, by level
+	Bound(usize),
+}
+
+/// Work left to do once the machine returns a value
+enum Frame {
+	/// Apply value to this thunk
+	Arg(usize),
+	/// Store value in this thunk
+	Update(usize),
+}
+
+enum Control<'e, 't> {
+	Eval(&'e Expr<'e>, Env<'t>),
+	Return(Value<'e, 't>),
+}
+
+struct Machine<'c, 'e, 't> {
+	config: &'c ReduceConfig,
+	trees: &'t LinkArena<'t>,
+	heap: Vec<Thunk<'e, 't>>,
+	steps: usize,
+	reused: usize,
+}
+impl<'c, 'e, 't> Machine<'c, 'e, 't> {
+	fn alloc(&mut self, thunk: Thunk<'e, 't>) -> usize {
+		self.heap.push(thunk);
+		self.heap.len() - 1
+	}
+	/// Start evaluating thunk, pushing an update frame if it hasn't been evaluated yet
+	fn enter(&mut self, thunk: usize, stack: &mut Vec<Frame>) -> Result<Control<'e, 't>, LambdaError> {
+		Ok(match std::mem::replace(&mut self.heap[thunk], Thunk::Evaluating) {
+			Thunk::Delayed(expr, env) => {
+				stack.push(Frame::Update(thunk));
+				Control::Eval(expr, env)
+			}
+			Thunk::Evaluated(value) => {
+				self.reused += 1;
+				self.heap[thunk] = Thunk::Evaluated(value.clone());
+				Control::Return(value)
+			}
+			Thunk::Bound(level) => {
+				self.heap[thunk] = Thunk::Bound(level);
+				Control::Return(Value::Neutral(Some(level), Vec::new()))
+			}
+			// A thunk can only refer to thunks that existed before it, so this can't happen
+			Thunk::Evaluating => return Err(LambdaError::BindingLevelMismatch),
+		})
+	}
+	/// Evaluate to weak head normal form, updating every thunk that is needed on the way
+	fn run(&mut self, mut control: Control<'e, 't>, stack: &mut Vec<Frame>) -> Result<Value<'e, 't>, LambdaError> {
+		loop {
+			if stack.len() >= self.config.recursion_limit {
+				return Err(LambdaError::RecursionDepthExceeded);
+			}
+			control = match control {
+				Control::Eval(Expr::Variable, env) => match env {
+					BindTree::End(thunk) => self.enter(*thunk, stack)?,
+					BindTree::None => Control::Return(Value::Neutral(None, Vec::new())),
+					BindTree::Branch(..) => return Err(LambdaError::UnexpectedBranchInSubstitution),
+				}
+				Control::Eval(Expr::Lambda { bind, expr }, env) => Control::Return(Value::Closure(bind, expr, env)),
+				Control::Eval(Expr::Application { func, args }, env) => {
+					let (func_env, args_env) = env.split()?;
+					let thunk = self.alloc(Thunk::Delayed(args, args_env));
+					stack.push(Frame::Arg(thunk));
+					Control::Eval(func, func_env)
+				}
+				Control::Return(value) => match (stack.pop(), value) {
+					(None, value) => return Ok(value),
+					(Some(Frame::Update(thunk)), value) => {
+						self.heap[thunk] = Thunk::Evaluated(value.clone());
+						Control::Return(value)
+					}
+					(Some(Frame::Arg(arg)), Value::Closure(bind, body, mut env)) => {
+						if self.config.fuel.is_some_and(|fuel| self.steps >= fuel) {
+							return Err(LambdaError::OutOfFuel(self.steps));
+						}
+						self.steps += 1;
+						env.push_binding(self.trees, arg, bind)?;
+						Control::Eval(body, env)
+					}
+					(Some(Frame::Arg(arg)), Value::Neutral(head, mut args)) => {
+						args.push(arg);
+						Control::Return(Value::Neutral(head, args))
+					}
+				}
+			}
+		}
+	}
+	/// Read value back into an expression, evaluating under lambdas and inside of arguments until it is in normal form
+	fn read_back(&mut self, value: Value<'e, 't>, exprs: &'e impl TypeStore<'e>) -> Result<&'e Expr<'e>, LambdaError> {
+		enum Task<'e, 't> {
+			Read(Value<'e, 't>),
+			Force(usize),
+			Lambda,
+			Application,
+		}
+		let binds = &LinkArena::new();
+		let mut level = 0;
+		let mut tasks = vec![Task::Read(value)];
+		let mut read: Vec<(&'e Expr<'e>, &BindSubTree)> = Vec::new();
+		while let Some(task) = tasks.pop() {
+			match task {
+				Task::Read(Value::Closure(bind, body, mut env)) => {
+					level += 1;
+					let thunk = self.alloc(Thunk::Bound(level));
+					env.push_binding(self.trees, thunk, bind)?;
+					let body = self.run(Control::Eval(body, env), &mut Vec::new())?;
+					tasks.push(Task::Lambda);
+					tasks.push(Task::Read(body));
+				}
+				Task::Read(Value::Neutral(head, args)) => {
+					read.push(match head {
+						Some(level) => (Expr::VAR, BindSubTree::end(level, binds)),
+						None => (Expr::VAR, BindSubTree::NONE),
+					});
+					for arg in args.into_iter().rev() {
+						tasks.push(Task::Application);
+						tasks.push(Task::Force(arg));
+					}
+				}
+				Task::Force(thunk) => {
+					let stack = &mut Vec::new();
+					let control = self.enter(thunk, stack)?;
+					tasks.push(Task::Read(self.run(control, stack)?));
+				}
+				Task::Lambda => {
+					let (body, mut tree) = read.pop().unwrap();
+					let bind = tree.pop_binding(binds, &level, exprs)?;
+					level -= 1;
+					read.push((Expr::lambda(bind, body, exprs), tree));
+				}
+				Task::Application => {
+					let (args, args_tree) = read.pop().unwrap();
+					let (func, func_tree) = read.pop().unwrap();
+					read.push((Expr::app(func, args, exprs), BindSubTree::branch(func_tree, args_tree, binds)));
+				}
+			}
+		}
+		Ok(read.pop().unwrap().0)
+	}
+}
+
+/// Outcome of reducing an expression with `Expr::reduce_lazy`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LazyReduction<'e> {
+	/// Normal form of the expression
+	pub expr: &'e Expr<'e>,
+	/// Number of beta reductions performed
+	pub steps: usize,
+	/// Number of times an already evaluated argument was needed again
+	pub reused: usize,
+}
+
+impl<'e> Expr<'e> {
+	/// Reduce expression to normal form using call-by-need, so every argument is evaluated at most once (up to weak head normal form).
+	/// Uses `fuel`, `recursion_limit` and `eta` from `config`, running out of fuel is an error.
+	pub fn reduce_lazy(self: &'e Expr<'e>, config: &ReduceConfig, exprs: &'e impl TypeStore<'e>) -> Result<LazyReduction<'e>, LambdaError> {
+		let trees = &LinkArena::new();
+		let mut machine = Machine { config, trees, heap: Vec::new(), steps: 0, reused: 0 };
+		let value = machine.run(Control::Eval(self, BindTree::NONE), &mut Vec::new())?;
+		let mut expr = machine.read_back(value, exprs)?;
+		if config.eta {
+			expr = expr.eta_reduce(exprs)?;
+		}
+		Ok(LazyReduction { expr, steps: machine.steps, reused: machine.reused })
+	}
+}
+
+#[test]
+fn reduce_lazy_test() {
+	use crate::{name::church_namespace, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &church_namespace(exprs);
+	let config = &ReduceConfig::default();
+
+	for string in ["[x] x", "[x y] y ([z] z x)", "([x] x x x) (([y] y) ([z] z))", "[a] ([x y] y) (([x] x x) ([x] x x)) a"] {
+		let expr = parse(string, namespace, exprs).unwrap();
+		assert_eq!(expr.reduce_lazy(config, exprs).unwrap().expr, expr.reduce(exprs).unwrap());
+	}
+
+	// Sum of 0..=4, `n` is used three times in every recursive call but only computed once
+	let expr = parse("Y ([r n] iszero n 0 (add n (r (pred n)))) 4", namespace, exprs).unwrap();
+	let lazy = expr.reduce_lazy(config, exprs).unwrap();
+	let substitution = expr.reduce_with(config, exprs).unwrap();
+	assert_eq!(lazy.expr, substitution.expr);
+	assert!(lazy.steps < substitution.steps);
+	assert!(lazy.reused > 0);
+
+	let omega = parse("([x] x x) ([x] x x)", namespace, exprs).unwrap();
+	assert!(matches!(omega.reduce_lazy(config, exprs), Err(LambdaError::OutOfFuel(_))));
+}
@@ -0,0 +1,471 @@
+//! Optimal reduction: expressions are translated into interaction nets and reduced with Lamping's algorithm,
+//! so that shared subexpressions (and redexes inside of them) are only ever reduced once.
+
+use std::rc::Rc;
+
+use hashdb::{LinkArena, TypeStore};
+
+use super::{BindSubTree, BindTree, Expr, LambdaError, ReduceConfig};
+
+/// Kinds of nodes in an interaction net
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Agent {
+	/// Where the expression's root is connected, never interacts
+	Root,
+	/// Ports: principal to parent, 1 to body, 2 to variable occurrences
+	Lambda,
+	/// Ports: principal to function, 1 to parent, 2 to arguments
+	App,
+	/// Shares whatever is at its principal port between its two auxiliary ports
+	Fan,
+	/// Opens a level, placed at every variable occurrence
+	Croissant,
+	/// Closes a level, placed wherever a variable leaves an argument
+	Bracket,
+	/// Deletes whatever it interacts with, used for unused variables
+	Eraser,
+	/// Unbound variable
+	Free,
+}
+impl Agent {
+	/// Number of auxiliary ports
+	fn arity(self) -> usize {
+		match self {
+			Agent::Root | Agent::Eraser | Agent::Free => 0,
+			Agent::Croissant | Agent::Bracket => 1,
+			Agent::Lambda | Agent::App | Agent::Fan => 2,
+		}
+	}
+	/// Level of a node after it passes through this agent
+	fn shift(self, level: usize) -> usize {
+		match self {
+			Agent::Croissant => level - 1,
+			Agent::Bracket => level + 1,
+			_ => level,
+		}
+	}
+}
+
+/// Port of a node, slot 0 is the principal port
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Port {
+	node: usize,
+	slot: usize,
+}
+impl Port {
+	fn new(node: usize, slot: usize) -> Self { Port { node, slot } }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+	agent: Agent,
+	level: usize,
+	ports: [Port; 3],
+}
+
+/// Part of a context (as in Gonthier, Abadi and Lévy's context semantics) used to pick the right branch at fans during readback
+#[derive(Debug)]
+enum Level {
+	/// Nothing known about this level
+	Any,
+	/// Opened by a croissant
+	Open,
+	/// Passed through auxiliary port 1 (false) or 2 (true) of a fan
+	Side(bool, Rc<Level>),
+	/// Two levels merged by a bracket
+	Pair(Rc<Level>, Rc<Level>),
+}
+
+impl Level {
+	/// Whether both could describe the same level, `Any` is compatible with everything
+	fn compatible(&self, other: &Level) -> bool {
+		match (self, other) {
+			(Level::Any, _) | (_, Level::Any) | (Level::Open, Level::Open) => true,
+			(Level::Side(a, a_rest), Level::Side(b, b_rest)) => a == b && a_rest.compatible(b_rest),
+			(Level::Pair(a_inner, a_outer), Level::Pair(b_inner, b_outer)) => a_inner.compatible(b_inner) && a_outer.compatible(b_outer),
+			_ => false,
+		}
+	}
+}
+
+/// Context at a port during readback, levels past the end are `Level::Any`
+#[derive(Clone, Debug, Default)]
+struct Context(Vec<Rc<Level>>);
+impl Context {
+	/// Whether the levels below `level` are compatible, meaning both contexts are at the same copy of a node at `level`
+	fn same_copy(&self, other: &Context, level: usize) -> bool {
+		(0..level).all(|level| self.get(level).compatible(&other.get(level)))
+	}
+	fn get(&self, level: usize) -> Rc<Level> {
+		self.0.get(level).cloned().unwrap_or_else(|| Rc::new(Level::Any))
+	}
+	fn pad(&mut self, len: usize) {
+		while self.0.len() < len { self.0.push(Rc::new(Level::Any)) }
+	}
+	fn set(&mut self, level: usize, value: Rc<Level>) {
+		self.pad(level + 1);
+		self.0[level] = value;
+	}
+	fn insert(&mut self, level: usize, value: Rc<Level>) {
+		self.pad(level);
+		self.0.insert(level, value);
+	}
+	fn remove(&mut self, level: usize) -> Rc<Level> {
+		if level < self.0.len() { self.0.remove(level) } else { Rc::new(Level::Any) }
+	}
+}
+
+/// What was found at the end of the head spine during readback
+enum Head {
+	Lambda(usize, Context),
+	Variable(usize, Context),
+	Free,
+}
+
+/// Interaction net with reduction counters
+struct Net {
+	nodes: Vec<Node>,
+	unused: Vec<usize>,
+	betas: usize,
+	interactions: usize,
+}
+impl Net {
+	const ROOT: Port = Port { node: 0, slot: 0 };
+	fn new() -> Self {
+		let mut net = Net { nodes: Vec::new(), unused: Vec::new(), betas: 0, interactions: 0 };
+		net.add(Agent::Root, 0);
+		net
+	}
+	fn add(&mut self, agent: Agent, level: usize) -> usize {
+		let node = Node { agent, level, ports: [Port::new(usize::MAX, 0); 3] };
+		match self.unused.pop() {
+			Some(index) => { self.nodes[index] = node; index }
+			None => { self.nodes.push(node); self.nodes.len() - 1 }
+		}
+	}
+	fn agent(&self, node: usize) -> Agent { self.nodes[node].agent }
+	fn partner(&self, port: Port) -> Port { self.nodes[port.node].ports[port.slot] }
+	fn link(&mut self, a: Port, b: Port) {
+		self.nodes[a.node].ports[a.slot] = b;
+		self.nodes[b.node].ports[b.slot] = a;
+	}
+
+	/// Translate expression, every lambda or application at level n takes its arguments at level n + 1
+	fn from_expr<'e>(expr: &'e Expr<'e>) -> Result<Net, LambdaError> {
+		let trees = &LinkArena::new();
+		let mut net = Net::new();
+		// Lambda nodes and the ports that their variable occurrences need to be connected to
+		let mut lambdas: Vec<(usize, Vec<Port>)> = Vec::new();
+
+		let mut stack = vec![(expr, BindSubTree::NONE, 0, Self::ROOT)];
+		while let Some((expr, tree, level, parent)) = stack.pop() {
+			match expr {
+				Expr::Variable => match tree {
+					BindTree::End(index) => {
+						let (lambda, occurrences) = &mut lambdas[*index - 1];
+						let croissant = net.add(Agent::Croissant, level);
+						net.link(parent, Port::new(croissant, 1));
+						// Leave every argument between the occurrence and the lambda
+						let mut port = Port::new(croissant, 0);
+						for level in (net.nodes[*lambda].level..level).rev() {
+							let bracket = net.add(Agent::Bracket, level);
+							net.link(port, Port::new(bracket, 1));
+							port = Port::new(bracket, 0);
+						}
+						occurrences.push(port);
+					}
+					BindTree::None => {
+						let free = net.add(Agent::Free, 0);
+						net.link(parent, Port::new(free, 0));
+					}
+					BindTree::Branch(..) => return Err(LambdaError::UnexpectedBranchInSubstitution),
+				}
+				Expr::Lambda { bind, expr } => {
+					let lambda = net.add(Agent::Lambda, level);
+					net.link(parent, Port::new(lambda, 0));
+					lambdas.push((lambda, Vec::new()));
+					let mut tree = tree;
+					tree.push_binding(trees, lambdas.len(), bind)?;
+					stack.push((expr, tree, level, Port::new(lambda, 1)));
+				}
+				Expr::Application { func, args } => {
+					let app = net.add(Agent::App, level);
+					net.link(parent, Port::new(app, 1));
+					let (func_tree, args_tree) = tree.split()?;
+					stack.push((args, args_tree, level + 1, Port::new(app, 2)));
+					stack.push((func, func_tree, level, Port::new(app, 0)));
+				}
+			}
+		}
+
+		// Share variables between their occurrences using fans at the level of the lambda
+		for (lambda, occurrences) in lambdas {
+			let level = net.nodes[lambda].level;
+			let mut port = Port::new(lambda, 2);
+			match occurrences.split_last() {
+				None => {
+					let eraser = net.add(Agent::Eraser, level);
+					net.link(port, Port::new(eraser, 0));
+				}
+				Some((last, rest)) => {
+					for occurrence in rest {
+						let fan = net.add(Agent::Fan, level);
+						net.link(port, Port::new(fan, 0));
+						net.link(Port::new(fan, 1), *occurrence);
+						port = Port::new(fan, 2);
+					}
+					net.link(port, *last);
+				}
+			}
+		}
+		Ok(net)
+	}
+
+	/// Whether two nodes connected by their principal ports can interact
+	fn active(&self, a: usize, b: usize) -> bool {
+		!matches!((self.agent(a), self.agent(b)),
+			(Agent::Root, _) | (_, Agent::Root)
+			| (Agent::Free, Agent::App | Agent::Lambda) | (Agent::App | Agent::Lambda, Agent::Free)
+		)
+	}
+
+	/// Rewrite active pair of nodes `x` and `y`
+	fn rewrite(&mut self, x: usize, y: usize) -> Result<(), LambdaError> {
+		use Agent::*;
+		let (nx, ny) = (self.nodes[x], self.nodes[y]);
+		// Connections to make, ports of `x` and `y` stand for whatever they were connected to
+		let mut links = Vec::new();
+		match (nx.agent, ny.agent) {
+			(Lambda, App) | (App, Lambda) => {
+				if nx.level != ny.level { return Err(LambdaError::InvalidNet("application and lambda at different levels")) }
+				let (lambda, app) = if nx.agent == Lambda { (x, y) } else { (y, x) };
+				links.push((Port::new(lambda, 1), Port::new(app, 1)));
+				links.push((Port::new(lambda, 2), Port::new(app, 2)));
+				self.betas += 1;
+			}
+			(Lambda | App, Lambda | App) => return Err(LambdaError::InvalidNet("lambdas or applications connected to each other")),
+			(Eraser | Free, Eraser | Free) => {}
+			(Eraser | Free, _) | (_, Eraser | Free) => {
+				let (copied, erased) = if matches!(nx.agent, Eraser | Free) { (nx.agent, y) } else { (ny.agent, x) };
+				for slot in 1..=self.agent(erased).arity() {
+					let node = self.add(copied, 0);
+					links.push((Port::new(node, 0), Port::new(erased, slot)));
+				}
+			}
+			(a, b) if a == b && nx.level == ny.level => {
+				for slot in 1..=a.arity() {
+					links.push((Port::new(x, slot), Port::new(y, slot)));
+				}
+			}
+			_ if nx.level == ny.level => return Err(LambdaError::InvalidNet("different agents interacting at the same level")),
+			_ => {
+				// The node with the higher level passes through the other one, each is copied once for each auxiliary port of the other
+				let (low, high) = if nx.level < ny.level { (nx, ny) } else { (ny, nx) };
+				let (low_node, high_node) = if nx.level < ny.level { (x, y) } else { (y, x) };
+				let high_copies = (0..low.agent.arity()).map(|_| self.add(high.agent, low.agent.shift(high.level))).collect::<Vec<_>>();
+				let low_copies = (0..high.agent.arity()).map(|_| self.add(low.agent, low.level)).collect::<Vec<_>>();
+				for (i, &high_copy) in high_copies.iter().enumerate() {
+					links.push((Port::new(high_copy, 0), Port::new(low_node, i + 1)));
+					for (j, &low_copy) in low_copies.iter().enumerate() {
+						links.push((Port::new(high_copy, j + 1), Port::new(low_copy, i + 1)));
+					}
+				}
+				for (j, &low_copy) in low_copies.iter().enumerate() {
+					links.push((Port::new(low_copy, 0), Port::new(high_node, j + 1)));
+				}
+			}
+		}
+
+		// Ports of `x` and `y` may be connected to each other, follow those wires until reaching a port that is kept
+		let removed = |port: Port| port.node == x || port.node == y;
+		let link_partner = |port: Port| links.iter().find_map(|&(a, b)| {
+			if a == port { Some(b) } else if b == port { Some(a) } else { None }
+		});
+		let resolve = |mut port: Port| {
+			for _ in 0..=links.len() {
+				if !removed(port) { return Some(port) }
+				let outside = self.partner(port);
+				if !removed(outside) { return Some(outside) }
+				port = link_partner(outside)?;
+			}
+			None
+		};
+		let resolved = links.iter().filter_map(|&(a, b)| Some((resolve(a)?, resolve(b)?))).collect::<Vec<_>>();
+		for (a, b) in resolved {
+			self.link(a, b);
+		}
+		self.unused.extend([x, y]);
+		self.interactions += 1;
+		Ok(())
+	}
+
+	/// Follow the head spine from `start`, reducing any active pairs on the way.
+	/// Returns the head and each application on the spine (outermost first) with the context it was reached in.
+	fn head(&mut self, start: Port, context: &Context, fuel: Option<usize>) -> Result<(Head, Vec<(usize, Context)>), LambdaError> {
+		'restart: loop {
+			let mut context = context.clone();
+			let mut spine = Vec::new();
+			let mut port = start;
+			loop {
+				let next = self.partner(port);
+				if port.slot == 0 && next.slot == 0 && self.active(port.node, next.node) {
+					let beta = matches!(self.agent(next.node), Agent::Lambda | Agent::App);
+					if beta && fuel.is_some_and(|fuel| self.betas >= fuel) {
+						return Err(LambdaError::OutOfFuel(self.betas));
+					}
+					self.rewrite(port.node, next.node)?;
+					continue 'restart;
+				}
+				let level = self.nodes[next.node].level;
+				port = match (self.agent(next.node), next.slot) {
+					(Agent::Lambda, 0) => return Ok((Head::Lambda(next.node, context), spine)),
+					(Agent::Lambda, 2) => return Ok((Head::Variable(next.node, context), spine)),
+					(Agent::Free, _) => return Ok((Head::Free, spine)),
+					(Agent::App, 1) => {
+						spine.push((next.node, context.clone()));
+						Port::new(next.node, 0)
+					}
+					(Agent::Fan, 0) => match &*context.get(level) {
+						Level::Side(side, rest) => {
+							let slot = if *side { 2 } else { 1 };
+							context.set(level, rest.clone());
+							Port::new(next.node, slot)
+						}
+						_ => return Err(LambdaError::InvalidNet("no context to pick fan branch")),
+					}
+					(Agent::Fan, slot) => {
+						let side = Rc::new(Level::Side(slot == 2, context.get(level)));
+						context.set(level, side);
+						Port::new(next.node, 0)
+					}
+					(Agent::Croissant, 0) => {
+						context.remove(level);
+						Port::new(next.node, 1)
+					}
+					(Agent::Croissant, _) => {
+						context.insert(level, Rc::new(Level::Open));
+						Port::new(next.node, 0)
+					}
+					(Agent::Bracket, 0) => {
+						let (inner, outer) = match &*context.get(level) {
+							Level::Pair(inner, outer) => (inner.clone(), outer.clone()),
+							_ => (Rc::new(Level::Any), Rc::new(Level::Any)),
+						};
+						context.set(level, inner);
+						context.insert(level + 1, outer);
+						Port::new(next.node, 1)
+					}
+					(Agent::Bracket, _) => {
+						let pair = Rc::new(Level::Pair(context.get(level), context.remove(level + 1)));
+						context.set(level, pair);
+						Port::new(next.node, 0)
+					}
+					_ => return Err(LambdaError::InvalidNet("unexpected port in head spine")),
+				}
+			}
+		}
+	}
+
+	/// Reduce net to normal form while reading it back into an expression
+	fn read_back<'e>(&mut self, fuel: Option<usize>, exprs: &'e impl TypeStore<'e>) -> Result<&'e Expr<'e>, LambdaError> {
+		enum Task {
+			Read(Port, Context),
+			Lambda,
+			Application,
+		}
+		let binds = &LinkArena::new();
+		// Lambdas enclosing the expression currently being read, with the context they were reached in
+		let mut lambdas: Vec<(usize, Context)> = Vec::new();
+		let mut tasks = vec![Task::Read(Self::ROOT, Context::default())];
+		let mut read: Vec<(&'e Expr<'e>, &BindSubTree)> = Vec::new();
+		while let Some(task) = tasks.pop() {
+			match task {
+				Task::Read(port, context) => {
+					let (head, spine) = self.head(port, &context, fuel)?;
+					for (app, context) in spine {
+						tasks.push(Task::Application);
+						tasks.push(Task::Read(Port::new(app, 2), context));
+					}
+					match head {
+						Head::Lambda(lambda, context) => {
+							lambdas.push((lambda, context.clone()));
+							tasks.push(Task::Lambda);
+							tasks.push(Task::Read(Port::new(lambda, 1), context));
+						}
+						Head::Variable(lambda, context) => {
+							// Copies of a lambda are the same node, reached in different contexts
+							let level = self.nodes[lambda].level;
+							let index = lambdas.iter()
+								.rposition(|(l, lambda_context)| *l == lambda && lambda_context.same_copy(&context, level))
+								.ok_or(LambdaError::InvalidNet("variable outside of its lambda"))?;
+							read.push((Expr::VAR, BindSubTree::end(index + 1, binds)));
+						}
+						Head::Free => read.push((Expr::VAR, BindSubTree::NONE)),
+					}
+				}
+				Task::Lambda => {
+					let (body, mut tree) = read.pop().unwrap();
+					let bind = tree.pop_binding(binds, &lambdas.len(), exprs)?;
+					lambdas.pop();
+					read.push((Expr::lambda(bind, body, exprs), tree));
+				}
+				Task::Application => {
+					let (args, args_tree) = read.pop().unwrap();
+					let (func, func_tree) = read.pop().unwrap();
+					read.push((Expr::app(func, args, exprs), BindSubTree::branch(func_tree, args_tree, binds)));
+				}
+			}
+		}
+		Ok(read.pop().unwrap().0)
+	}
+}
+
+/// Outcome of reducing an expression with `Expr::reduce_optimal`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OptimalReduction<'e> {
+	/// Normal form of the expression
+	pub expr: &'e Expr<'e>,
+	/// Number of beta reductions, counting each shared redex once
+	pub betas: usize,
+	/// Number of interactions, including the ones that duplicate or erase parts of the net
+	pub interactions: usize,
+}
+
+impl<'e> Expr<'e> {
+	/// Reduce expression to normal form using optimal reduction.
+	/// Only `fuel` (bounding the number of beta reductions) and `eta` from `config` are used, the strategy is always lazy and complete.
+	pub fn reduce_optimal(self: &'e Expr<'e>, config: &ReduceConfig, exprs: &'e impl TypeStore<'e>) -> Result<OptimalReduction<'e>, LambdaError> {
+		let mut net = Net::from_expr(self)?;
+		let mut expr = net.read_back(config.fuel, exprs)?;
+		if config.eta {
+			expr = expr.eta_reduce(exprs)?;
+		}
+		Ok(OptimalReduction { expr, betas: net.betas, interactions: net.interactions })
+	}
+}
+
+#[test]
+fn reduce_optimal_test() {
+	use crate::{name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let parsed = |string| parse(string, namespace, exprs).unwrap();
+	let config = &ReduceConfig { fuel: None, ..Default::default() };
+
+	for string in ["[x] x", "[x y] y x", "([x y] x) ([x y] y) ([x y] x)", "[f] ([x] f x x) ([y] y)", "[a] ([x] x x) ([y] a y)"] {
+		let expr = parsed(string);
+		assert_eq!(expr.reduce_optimal(config, exprs).unwrap().expr, expr.reduce(exprs).unwrap());
+	}
+
+	// `2 2 2` is 16, which takes many more beta reductions to compute without sharing
+	let two = "([f x] f (f x))";
+	let expr = parse(&[two; 3].join(" "), namespace, exprs).unwrap();
+	let optimal = expr.reduce_optimal(config, exprs).unwrap();
+	let substitution = expr.reduce_with(config, exprs).unwrap();
+	assert_eq!(optimal.expr, substitution.expr);
+	assert!(optimal.betas < substitution.steps);
+
+	let omega = parsed("([x] x x) ([x] x x)");
+	assert!(matches!(omega.reduce_optimal(&ReduceConfig::default(), exprs), Err(LambdaError::OutOfFuel(_))));
+}
@@ -0,0 +1,479 @@
+//! Normal order reduction that reduces the arguments of applications whose head is a variable on multiple threads
+
+use std::{
+	collections::HashMap,
+	num::NonZeroUsize,
+	sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+	thread::{self, ScopedJoinHandle},
+};
+
+use hashdb::{LinkArena, TypeStore};
+
+use super::{BindIndex, BindSubTree, BindTree, Binding, ByAddress, Expr, LambdaError, ReduceConfig, ReduceState, ReduceStats, Reduction, ReductionStrategy};
+
+/// Head normal form `[x ..] v a ..` of a subexpression, with its arguments normalized
+struct HeadNormal<'s> {
+	/// Beta reductions done to reach the head normal form
+	steps: usize,
+	stats: ReduceStats,
+	lambdas: usize,
+	/// Binding index of the head variable
+	head: BindIndex<'s>,
+	args: Vec<Normalized<'s>>,
+}
+
+/// What became of a subexpression reduced in parallel
+enum Outcome<'s> {
+	/// Reduced to head normal form on this thread
+	Head(HeadNormal<'s>),
+	/// Reduced to normal form on another thread
+	Normal(Resolved<'s>),
+	/// Head reduction failed or was stopped
+	Failed,
+}
+
+/// Subexpression reduced in parallel, kept so it can be redone sequentially if the sequential reducer would stop inside it
+struct Normalized<'s> {
+	expr: &'s Expr<'s>,
+	bind_index: BindIndex<'s>,
+	/// Number of frames the sequential reducer would have on its stack when reaching the subexpression
+	depth: usize,
+	outcome: Outcome<'s>,
+}
+impl<'s> Drop for Normalized<'s> {
+	// Arguments can be nested as deep as the expression, so they are dropped with an explicit stack
+	fn drop(&mut self) {
+		let mut args = Vec::new();
+		let mut outcome = std::mem::replace(&mut self.outcome, Outcome::Failed);
+		loop {
+			if let Outcome::Head(head) = outcome {
+				args.extend(head.args);
+			}
+			let Some(mut arg) = args.pop() else { break };
+			outcome = std::mem::replace(&mut arg.outcome, Outcome::Failed);
+		}
+	}
+}
+
+/// Expression with its binding index, as the sequential reducer would have left it
+struct Resolved<'s> {
+	expr: &'s Expr<'s>,
+	bind_index: BindIndex<'s>,
+	/// Beta reductions performed
+	steps: usize,
+	out_of_fuel: bool,
+	stats: ReduceStats,
+}
+
+/// Node of a `Detached` expression, referring to other nodes by index
+#[derive(Clone, Copy)]
+enum ExprNode {
+	Variable,
+	Lambda(usize, usize),
+	Application(usize, usize),
+}
+/// Node of a `Detached` binding
+#[derive(Clone, Copy)]
+enum BindNode {
+	None,
+	End,
+	Branch(usize, usize),
+}
+/// Node of a `Detached` binding tree
+#[derive(Clone, Copy)]
+enum TreeNode {
+	None,
+	End(usize),
+	Branch(usize, usize),
+}
+
+/// Normal form copied out of the arena of the thread that found it, so it can be sent to the thread that needs it.
+/// Nodes only refer to nodes before them and the last ones are the roots.
+struct Detached {
+	exprs: Vec<ExprNode>,
+	binds: Vec<BindNode>,
+	trees: Vec<TreeNode>,
+	index: usize,
+	steps: usize,
+	stats: ReduceStats,
+}
+impl Detached {
+	fn new(resolved: &Resolved<'_>) -> Self {
+		let mut detached = Detached { exprs: Vec::new(), binds: Vec::new(), trees: Vec::new(), index: resolved.bind_index.index, steps: resolved.steps, stats: resolved.stats };
+		let mut bind_ids = HashMap::new();
+		let binds = &mut detached.binds;
+		detach(resolved.expr, &mut HashMap::new(), &mut detached.exprs, |expr| match expr {
+			Expr::Variable => [None, None],
+			Expr::Lambda { expr, .. } => [Some(*expr), None],
+			Expr::Application { func, args } => [Some(*func), Some(*args)],
+		}, |expr, ids| match expr {
+			Expr::Variable => ExprNode::Variable,
+			Expr::Lambda { bind, expr } => ExprNode::Lambda(detach_binding(bind, &mut bind_ids, binds), ids[&ByAddress(*expr)]),
+			Expr::Application { func, args } => ExprNode::Application(ids[&ByAddress(*func)], ids[&ByAddress(*args)]),
+		});
+		detach(resolved.bind_index.tree, &mut HashMap::new(), &mut detached.trees, |tree| match tree {
+			BindTree::Branch(left, right) => [Some(*left), Some(*right)],
+			_ => [None, None],
+		}, |tree, ids| match tree {
+			BindTree::None => TreeNode::None,
+			BindTree::End(level) => TreeNode::End(*level),
+			BindTree::Branch(left, right) => TreeNode::Branch(ids[&ByAddress(*left)], ids[&ByAddress(*right)]),
+		});
+		detached
+	}
+	/// Build the normal form again in `arena`
+	fn attach<'a>(&self, arena: &'a impl TypeStore<'a>) -> Resolved<'a> {
+		let mut binds: Vec<&'a Binding<'a>> = Vec::with_capacity(self.binds.len());
+		for node in &self.binds {
+			let bind = match *node {
+				BindNode::None => Binding::NONE,
+				BindNode::End => Binding::END,
+				BindNode::Branch(left, right) => Binding::branch(binds[left], binds[right], arena),
+			};
+			binds.push(bind);
+		}
+		let mut exprs: Vec<&'a Expr<'a>> = Vec::with_capacity(self.exprs.len());
+		for node in &self.exprs {
+			let expr = match *node {
+				ExprNode::Variable => Expr::VAR,
+				ExprNode::Lambda(bind, expr) => Expr::lambda(binds[bind], exprs[expr], arena),
+				ExprNode::Application(func, args) => Expr::app(exprs[func], exprs[args], arena),
+			};
+			exprs.push(expr);
+		}
+		let mut trees: Vec<&'a BindSubTree<'a>> = Vec::with_capacity(self.trees.len());
+		for node in &self.trees {
+			let tree = match *node {
+				TreeNode::None => BindTree::NONE,
+				TreeNode::End(level) => BindTree::end(level, arena),
+				TreeNode::Branch(left, right) => BindTree::branch(trees[left], trees[right], arena),
+			};
+			trees.push(tree);
+		}
+		Resolved {
+			expr: exprs.last().unwrap(),
+			bind_index: BindIndex::new(self.index, trees.last().unwrap()),
+			steps: self.steps,
+			out_of_fuel: false,
+			stats: self.stats,
+		}
+	}
+}
+
+/// Copy graph into `nodes` so that every node comes after the nodes it refers to, visiting shared nodes once. Returns the index of `root`.
+fn detach<'s, T, N>(
+	root: &'s T,
+	ids: &mut HashMap<ByAddress<'s, T>, usize>,
+	nodes: &mut Vec<N>,
+	children: impl Fn(&'s T) -> [Option<&'s T>; 2],
+	mut node: impl FnMut(&'s T, &HashMap<ByAddress<'s, T>, usize>) -> N,
+) -> usize {
+	let mut tasks = vec![(root, false)];
+	while let Some((value, children_detached)) = tasks.pop() {
+		if ids.contains_key(&ByAddress(value)) { continue }
+		if !children_detached {
+			tasks.push((value, true));
+			tasks.extend(children(value).into_iter().flatten().rev().map(|child| (child, false)));
+			continue
+		}
+		let detached = node(value, ids);
+		ids.insert(ByAddress(value), nodes.len());
+		nodes.push(detached);
+	}
+	ids[&ByAddress(root)]
+}
+fn detach_binding<'s>(bind: &'s Binding<'s>, ids: &mut HashMap<ByAddress<'s, Binding<'s>>, usize>, nodes: &mut Vec<BindNode>) -> usize {
+	detach(bind, ids, nodes, |bind| match bind {
+		Binding::Branch(left, right) => [Some(*left), Some(*right)],
+		_ => [None, None],
+	}, |bind, ids| match bind {
+		Binding::None => BindNode::None,
+		Binding::End => BindNode::End,
+		Binding::Branch(left, right) => BindNode::Branch(ids[&ByAddress(*left)], ids[&ByAddress(*right)]),
+	})
+}
+
+/// State shared between all threads of a reduction
+struct Shared<'c> {
+	config: &'c ReduceConfig,
+	/// Beta reductions performed by all threads, including ones the sequential reducer wouldn't have done
+	steps: AtomicUsize,
+	/// Number of threads that may still be started
+	spare_threads: AtomicUsize,
+	/// Set once a head reduction fails or the fuel is used up, what is left is reduced sequentially by `resolve`
+	stopped: AtomicBool,
+}
+impl<'c> Shared<'c> {
+	fn claim_thread(&self) -> bool {
+		self.spare_threads.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |spare| spare.checked_sub(1)).is_ok()
+	}
+	/// Reduce to head normal form with the fuel that is left and split it into lambdas, head and arguments.
+	/// Returns None if that fails, the error is found again when the subexpression is redone sequentially.
+	fn head_normal_form<'s>(
+		&self,
+		expr: &'s Expr<'s>,
+		mut bind_index: BindIndex<'s>,
+		depth: usize,
+		arena: &'s LinkArena<'s>
+	) -> Option<(HeadNormal<'s>, Vec<(&'s Expr<'s>, BindIndex<'s>)>)> {
+		if self.stopped.load(Ordering::Relaxed) { return None }
+		let config = &ReduceConfig {
+			strategy: ReductionStrategy::HeadNormalForm,
+			fuel: self.config.fuel.map(|fuel| fuel.saturating_sub(self.steps.load(Ordering::Relaxed))),
+			recursion_limit: self.config.recursion_limit.saturating_sub(depth),
+			..self.config.clone()
+		};
+		let state = &mut ReduceState::new(config, None, None);
+		let reduced = expr.partial_reduce(&mut bind_index, config.strategy, state, arena, arena);
+		let steps = self.steps.fetch_add(state.steps, Ordering::Relaxed) + state.steps;
+		if reduced.is_err() || state.out_of_fuel || self.config.fuel.is_some_and(|fuel| steps >= fuel) {
+			self.stopped.store(true, Ordering::Relaxed);
+		}
+		let mut head = reduced.ok().filter(|_| !state.out_of_fuel)?;
+
+		let mut lambdas = 0;
+		while let Expr::Lambda { bind, expr } = head {
+			bind_index.push_binding(bind, arena).ok()?;
+			lambdas += 1;
+			head = expr;
+		}
+		let mut args = Vec::new();
+		while let Expr::Application { func, args: arg } = head {
+			let (func_bind, args_bind) = bind_index.split().ok()?;
+			args.push((*arg, args_bind));
+			bind_index = func_bind;
+			head = func;
+		}
+		args.reverse();
+		let stats = state.stats(expr);
+		Some((HeadNormal { steps: state.steps, stats, lambdas, head: bind_index, args: Vec::new() }, args))
+	}
+}
+
+/// Argument of a head normal form that `normalize` is waiting for
+enum Pending<'s, 't> {
+	/// Normalized on this thread, its result is on the `normalized` stack
+	Inline,
+	/// Normalized on another thread, which only sends back the normal form
+	Thread(&'s Expr<'s>, BindIndex<'s>, usize, ScopedJoinHandle<'t, Option<Detached>>),
+}
+
+/// Work left to do in `normalize`
+enum Task<'s, 't> {
+	/// Reduce subexpression to normal form, the sequential reducer would have `depth` frames on its stack when reaching it
+	Normalize(&'s Expr<'s>, BindIndex<'s>, usize),
+	/// Collect the normalized arguments of a head normal form
+	Collect(Normalized<'s>, Vec<Pending<'s, 't>>),
+}
+
+/// Reduce expression to normal form by reducing it to head normal form and then normalizing its arguments,
+/// starting a new thread for every argument but the last while there are spare threads.
+/// Every thread reduces in its own arena and sends back a copy of the normal form it found.
+fn normalize<'s>(expr: &'s Expr<'s>, bind_index: BindIndex<'s>, depth: usize, shared: &Shared, arena: &'s LinkArena<'s>) -> Normalized<'s> {
+	thread::scope(|scope| {
+		let mut tasks = vec![Task::Normalize(expr, bind_index, depth)];
+		let mut normalized: Vec<Normalized<'s>> = Vec::new();
+		while let Some(task) = tasks.pop() {
+			match task {
+				Task::Normalize(expr, bind_index, depth) => {
+					let Some((head, args)) = shared.head_normal_form(expr, bind_index.clone(), depth, arena) else {
+						normalized.push(Normalized { expr, bind_index, depth, outcome: Outcome::Failed });
+						continue
+					};
+					// Frames the sequential reducer would have while reducing argument `i`: one per lambda, one per later argument and one for this one
+					let arg_depth = |i: usize| depth + head.lambdas + args.len() - i;
+					let mut inline = Vec::new();
+					let mut pending = Vec::new();
+					let last = args.len().saturating_sub(1);
+					for (i, (arg, args_bind)) in args.iter().cloned().enumerate() {
+						let arg_depth = arg_depth(i);
+						if i != last && shared.claim_thread() {
+							let thread_bind = args_bind.clone();
+							pending.push(Pending::Thread(arg, args_bind, arg_depth, scope.spawn(move || {
+								let arena = &LinkArena::new();
+								let normalized = normalize(arg, thread_bind, arg_depth, shared, arena);
+								let detached = resolve(&normalized, shared.config, false, arena).ok().flatten().map(|resolved| Detached::new(&resolved));
+								shared.spare_threads.fetch_add(1, Ordering::Relaxed);
+								detached
+							})));
+						} else {
+							pending.push(Pending::Inline);
+							inline.push(Task::Normalize(arg, args_bind, arg_depth));
+						}
+					}
+					tasks.push(Task::Collect(Normalized { expr, bind_index, depth, outcome: Outcome::Head(head) }, pending));
+					tasks.extend(inline.into_iter().rev());
+				}
+				Task::Collect(mut node, pending) => {
+					let inline = pending.iter().filter(|arg| matches!(arg, Pending::Inline)).count();
+					let mut inline = normalized.split_off(normalized.len() - inline).into_iter();
+					let args = pending.into_iter().map(|arg| match arg {
+						Pending::Inline => inline.next().unwrap(),
+						Pending::Thread(expr, bind_index, depth, handle) => {
+							let outcome = match handle.join().unwrap() {
+								Some(detached) => Outcome::Normal(detached.attach(arena)),
+								None => Outcome::Failed,
+							};
+							Normalized { expr, bind_index, depth, outcome }
+						}
+					});
+					let Outcome::Head(head) = &mut node.outcome else { unreachable!() };
+					head.args.extend(args);
+					normalized.push(node);
+				}
+			}
+		}
+		normalized.pop().unwrap()
+	})
+}
+
+/// Find the result the sequential reducer would have: results are used in the order it would have reached them while they fit in the fuel left,
+/// a subexpression whose reduction failed or doesn't fit is reduced again sequentially, unless `redo` is false in which case None is returned.
+fn resolve<'s>(root: &Normalized<'s>, config: &ReduceConfig, redo: bool, arena: &'s LinkArena<'s>) -> Result<Option<Resolved<'s>>, LambdaError> {
+	enum Task<'n, 's> {
+		Resolve(&'n Normalized<'s>),
+		/// Re-wrap head variable with resolved arguments and lambdas
+		Rebuild { lambdas: usize, head: BindIndex<'s>, args: usize },
+	}
+	let mut steps = 0;
+	let mut out_of_fuel = false;
+	let mut stats = ReduceStats::default();
+	let mut tasks = vec![Task::Resolve(root)];
+	let mut resolved: Vec<(&'s Expr<'s>, BindIndex<'s>)> = Vec::new();
+	while let Some(task) = tasks.pop() {
+		match task {
+			Task::Resolve(node) => {
+				let fuel = config.fuel.map(|fuel| fuel - steps);
+				let fits = |steps: usize| fuel.is_none_or(|fuel| steps <= fuel);
+				match &node.outcome {
+					Outcome::Head(head) if fits(head.steps) => {
+						steps += head.steps;
+						stats.merge(&head.stats);
+						tasks.push(Task::Rebuild { lambdas: head.lambdas, head: head.head.clone(), args: head.args.len() });
+						tasks.extend(head.args.iter().rev().map(Task::Resolve));
+					}
+					Outcome::Normal(normal) if fits(normal.steps) => {
+						steps += normal.steps;
+						stats.merge(&normal.stats);
+						resolved.push((normal.expr, normal.bind_index.clone()));
+					}
+					_ if !redo => return Ok(None),
+					_ => {
+						let config = &ReduceConfig {
+							strategy: ReductionStrategy::NormalOrder,
+							fuel,
+							recursion_limit: config.recursion_limit.saturating_sub(node.depth),
+							..config.clone()
+						};
+						let state = &mut ReduceState::new(config, None, None);
+						let mut bind_index = node.bind_index.clone();
+						let expr = node.expr.partial_reduce(&mut bind_index, config.strategy, state, arena, arena)?;
+						steps += state.steps;
+						stats.merge(&state.stats(node.expr));
+						out_of_fuel |= state.out_of_fuel;
+						resolved.push((expr, bind_index));
+					}
+				}
+			}
+			Task::Rebuild { lambdas, head, args } => {
+				let args = resolved.split_off(resolved.len() - args);
+				let (mut expr, mut bind_index) = (Expr::VAR, head);
+				for (arg, args_bind) in args {
+					expr = Expr::app(expr, arg, arena);
+					bind_index = BindIndex::join(bind_index, args_bind, arena);
+				}
+				for _ in 0..lambdas {
+					expr = Expr::lambda(bind_index.pop_binding(arena, arena)?, expr, arena);
+				}
+				resolved.push((expr, bind_index));
+			}
+		}
+	}
+	let (expr, bind_index) = resolved.pop().unwrap();
+	Ok(Some(Resolved { expr, bind_index, steps, out_of_fuel, stats }))
+}
+
+impl<'e> Expr<'e> {
+	/// Reduce expression like `reduce_with`, but reduce the arguments of applications whose head is a variable on multiple threads.
+	/// Only normal order reduction is done in parallel. Where the sequential reducer would run out of fuel or fail, that subexpression is redone sequentially,
+	/// so the result is always the same as that of `reduce_with`. Only the measured allocations can differ, they are counted per head reduction.
+	pub fn reduce_parallel(self: &'e Expr<'e>, config: &ReduceConfig, exprs: &'e impl TypeStore<'e>) -> Result<Reduction<'e>, LambdaError> {
+		if config.strategy != ReductionStrategy::NormalOrder {
+			return self.reduce_with(config, exprs);
+		}
+		let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+		let shared = Shared {
+			config,
+			steps: AtomicUsize::new(0),
+			spare_threads: AtomicUsize::new(threads - 1),
+			stopped: AtomicBool::new(false),
+		};
+		let arena = &LinkArena::new();
+		let normalized = normalize(self, BindIndex::DEFAULT.clone(), 0, &shared, arena);
+		// Everything can be redone, so there is always a result
+		let resolved = resolve(&normalized, config, true, arena)?.unwrap();
+		let mut expr = Detached::new(&resolved).attach(exprs).expr;
+		if config.eta && !resolved.out_of_fuel {
+			expr = expr.eta_reduce(exprs)?;
+		}
+		Ok(Reduction { expr, steps: resolved.steps, out_of_fuel: resolved.out_of_fuel, stats: config.stats.then_some(resolved.stats) })
+	}
+}
+
+#[test]
+fn reduce_parallel_test() {
+	use crate::{name::church_namespace, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &church_namespace(exprs);
+
+	// List of squares, every element is reduced independently of the others
+	let list = (0..12).fold("nil".to_owned(), |list, i| format!("cons (mul {i} {i}) ({list})"));
+	let expr = parse(&list, namespace, exprs).unwrap();
+	let config = &ReduceConfig { fuel: None, ..Default::default() };
+	assert_eq!(expr.reduce_parallel(config, exprs).unwrap(), expr.reduce_with(config, exprs).unwrap());
+
+	// Normal forms found on other threads are sent back as copies
+	let reduction = expr.reduce_with(config, exprs).unwrap();
+	let resolved = Resolved { expr: reduction.expr, bind_index: BindIndex::DEFAULT.clone(), steps: reduction.steps, out_of_fuel: false, stats: ReduceStats::default() };
+	let other = &LinkArena::new();
+	let attached = Detached::new(&resolved).attach(other);
+	assert_eq!((attached.expr, attached.steps), (reduction.expr, reduction.steps));
+
+	// Results match wherever reduction stops
+	for config in [0, 1, 7, 40, 100, 333].map(|fuel| ReduceConfig { fuel: Some(fuel), ..Default::default() }).into_iter().chain([
+		ReduceConfig { recursion_limit: 20, ..Default::default() },
+		ReduceConfig { strategy: ReductionStrategy::ApplicativeOrder, ..Default::default() },
+	]) {
+		let parallel = expr.reduce_parallel(&config, exprs).map_err(|err| err.to_string());
+		assert_eq!(parallel, expr.reduce_with(&config, exprs).map_err(|err| err.to_string()));
+	}
+
+	let expr = parse("[x] x 1 (([y] y y) ([y] y y)) 2", namespace, exprs).unwrap();
+	assert!(matches!(expr.reduce_parallel(&ReduceConfig::default(), exprs), Err(LambdaError::Diverges(1))));
+}
+
+/// Times `reduce_parallel` against `reduce_with` on a long church list, run with `cargo test --release reduce_parallel_bench -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn reduce_parallel_bench() {
+	use std::time::Instant;
+	use crate::{name::church_namespace, parse::parse};
+
+	let list = (0..64).fold("nil".to_owned(), |list, i| format!("cons (pred (mul {} 24)) ({list})", i % 8 + 16));
+	let config = &ReduceConfig { fuel: None, ..Default::default() };
+	// Each engine gets an arena of its own, so neither finds nodes the other built
+	let time = |parallel: bool| {
+		let exprs = &LinkArena::new();
+		let expr = parse(&list, &church_namespace(exprs), exprs).unwrap();
+		let start = Instant::now();
+		let reduction = if parallel { expr.reduce_parallel(config, exprs) } else { expr.reduce_with(config, exprs) }.unwrap();
+		(start.elapsed(), reduction.steps, reduction.expr.to_string())
+	};
+	let (sequential_time, steps, sequential) = time(false);
+	let (parallel_time, parallel_steps, parallel) = time(true);
+	assert_eq!((parallel_steps, parallel), (steps, sequential));
+
+	let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+	println!("{steps} beta reductions: reduce_with took {sequential_time:?}, reduce_parallel took {parallel_time:?} on {threads} threads");
+}
@@ -0,0 +1,350 @@
+//! Prints expressions with generated variable names, wrapping long terms to a line width.
+//! Subterms can be folded back into the names they are defined as.
+
+use std::{collections::{HashMap, HashSet}, fmt, str::FromStr};
+
+use hashdb::LinkArena;
+
+use crate::name::{NamedObject, NamespaceMut};
+
+use super::{decode::canonical_nat, BindSubTree, BindTree, ByAddress, Expr, LambdaError};
+
+/// Name printed for variables that aren't bound by any lambda
+const FREE_NAME: &str = "free";
+
+/// Syntax used for lambdas
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Default)]
+pub enum Notation {
+	/// `[a b] a b`
+	#[default]
+	Brackets,
+	/// `λa b. a b`
+	Lambda,
+	/// `\a b. a b`
+	Backslash,
+	/// `fun a b -> a b`
+	Fun,
+	/// `\lambda a\,b.\, a\ b` for LaTeX math mode, never wrapped as LaTeX does that itself
+	Latex,
+}
+impl Notation {
+	pub const ALL: [Notation; 5] = [Self::Brackets, Self::Lambda, Self::Backslash, Self::Fun, Self::Latex];
+	/// Short name, used when selecting a notation in the cli
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Brackets => "brackets",
+			Self::Lambda => "lambda",
+			Self::Backslash => "backslash",
+			Self::Fun => "fun",
+			Self::Latex => "latex",
+		}
+	}
+	/// Start of lambda header
+	fn open(self) -> &'static str {
+		match self {
+			Self::Brackets => "[",
+			Self::Lambda => "λ",
+			Self::Backslash => "\\",
+			Self::Fun => "fun ",
+			Self::Latex => "\\lambda ",
+		}
+	}
+	/// Between variables of lambda header
+	fn between(self) -> &'static str {
+		match self {
+			Self::Latex => "\\,",
+			_ => " ",
+		}
+	}
+	/// End of lambda header, including the space before the body
+	fn close(self) -> &'static str {
+		match self {
+			Self::Brackets => "] ",
+			Self::Lambda | Self::Backslash => ". ",
+			Self::Fun => " -> ",
+			Self::Latex => ".\\, ",
+		}
+	}
+	/// Between function and argument
+	fn space(self) -> &'static str {
+		match self {
+			Self::Latex => "\\ ",
+			_ => " ",
+		}
+	}
+}
+impl fmt::Display for Notation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+impl FromStr for Notation {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL.into_iter().find(|notation| notation.name() == s).ok_or_else(|| {
+			let names = Self::ALL.map(Self::name).join(", ");
+			format!("unknown notation `{s}`, expected one of: {names}")
+		})
+	}
+}
+
+/// Name of the variable bound by the lambda at `level`: `a` to `z`, then `a1` to `z1` and so on
+fn var_name(level: usize) -> String {
+	let letter = (b'a' + ((level - 1) % 26) as u8) as char;
+	match (level - 1) / 26 {
+		0 => letter.to_string(),
+		n => format!("{letter}{n}"),
+	}
+}
+
+/// Node of a term being printed, in prefix order
+enum Node {
+	/// Variable, name or numeral
+	Atom(String),
+	/// Lambda binding the name, followed by its body
+	Lambda(String),
+	/// Application, followed by its function and then its argument
+	App,
+}
+
+/// Print nodes using as few parentheses as possible, applications that don't fit in `width` columns are broken up with one argument per line
+fn layout(nodes: &[Node], notation: Notation, width: usize) -> String {
+	// Width of each subterm printed on one line, children come after their parents so go from the end
+	let mut widths = vec![0; nodes.len()];
+	let mut stack: Vec<(usize, &Node)> = Vec::new();
+	for (i, node) in nodes.iter().enumerate().rev() {
+		widths[i] = match node {
+			Node::Atom(name) => name.chars().count(),
+			Node::Lambda(name) => match stack.pop().unwrap() {
+				// `[a` followed by ` b ..] body`
+				(body, Node::Lambda(_)) => name.chars().count() + notation.between().chars().count() + body,
+				// `[a] body`
+				(body, _) => notation.open().chars().count() + name.chars().count() + notation.close().chars().count() + body,
+			}
+			Node::App => {
+				let (func, func_node) = stack.pop().unwrap();
+				let (args, args_node) = stack.pop().unwrap();
+				let parens = 2 * (matches!(func_node, Node::Lambda(_)) as usize + matches!(args_node, Node::Lambda(_) | Node::App) as usize);
+				func + notation.space().chars().count() + args + parens
+			}
+		};
+		stack.push((widths[i], node));
+	}
+
+	#[derive(Clone, Copy, PartialEq)]
+	enum Kind {
+		Term,
+		/// Body of a lambda whose header is still open
+		Body,
+		Func,
+		Args,
+		Close,
+	}
+	/// Place for a subterm, `broken` applications put each argument on its own line
+	struct Slot {
+		kind: Kind,
+		indent: usize,
+		broken: bool,
+	}
+	let mut out = String::new();
+	let mut column = 0;
+	let mut write = |out: &mut String, string: &str| {
+		out.push_str(string);
+		column = match string.rfind('\n') {
+			Some(newline) => string[newline + 1..].chars().count(),
+			None => column + string.chars().count(),
+		};
+		column
+	};
+	let mut slots = vec![Slot { kind: Kind::Term, indent: 0, broken: false }];
+	for (i, node) in nodes.iter().enumerate() {
+		let slot = slots.pop().unwrap();
+		match slot.kind {
+			Kind::Body if !matches!(node, Node::Lambda(_)) => { write(&mut out, notation.close()); }
+			Kind::Args if slot.broken => { write(&mut out, &format!("\n{:1$}", "", slot.indent)); }
+			Kind::Args => { write(&mut out, notation.space()); }
+			_ => {}
+		}
+		let parens = matches!((slot.kind, node), (Kind::Func, Node::Lambda(_)) | (Kind::Args, Node::Lambda(_) | Node::App));
+		let mut start = write(&mut out, "");
+		if parens {
+			start = write(&mut out, "(");
+			slots.push(Slot { kind: Kind::Close, ..slot });
+		}
+		match node {
+			Node::Atom(name) => { write(&mut out, name); }
+			Node::Lambda(name) => {
+				let separator = if slot.kind == Kind::Body { notation.between() } else { notation.open() };
+				write(&mut out, separator);
+				write(&mut out, name);
+				slots.push(Slot { kind: Kind::Body, ..slot });
+			}
+			Node::App => {
+				// Functions of broken applications are applications of the same spine, so their arguments go on the same separate lines
+				let broken = (slot.kind == Kind::Func && slot.broken) || (notation != Notation::Latex && start + widths[i] + parens as usize > width);
+				let indent = if slot.kind == Kind::Func { slot.indent } else { slot.indent + 2 };
+				slots.push(Slot { kind: Kind::Args, indent, broken });
+				slots.push(Slot { kind: Kind::Func, indent, broken });
+			}
+		}
+		while slots.last().is_some_and(|slot| slot.kind == Kind::Close) {
+			slots.pop();
+			write(&mut out, ")");
+		}
+	}
+	out
+}
+
+/// Numeral `n` if expression is `succ (succ .. zero)` or the church numeral it reduces to
+fn numeral<'e>(expr: &'e Expr<'e>, zero: &'e Expr<'e>, succ: &'e Expr<'e>) -> Option<u64> {
+	let mut n = 0;
+	let mut base = expr;
+	while let Expr::Application { func, args } = base {
+		if !std::ptr::eq(*func, succ) { break }
+		n += 1;
+		base = args;
+	}
+	if std::ptr::eq(base, zero) { return Some(n) }
+	if canonical_nat(zero) == Some(0) { canonical_nat(expr) } else { None }
+}
+
+impl<'e> Expr<'e> {
+	/// Print with variables named after the depth of their lambda (the outermost binds `a`), using as few parentheses as possible.
+	/// Applications that don't fit in `width` columns are broken up with one argument per line.
+	/// Printed in any notation but `Notation::Latex` it parses back to the same expression, as long as the namespace doesn't define `free`, which is the name of free variables.
+	pub fn pretty(self: &'e Expr<'e>, notation: Notation, width: usize) -> Result<String, LambdaError> {
+		self.pretty_folded(notation, width, |_| None, |_| false)
+	}
+	/// Like `pretty`, but subterms that are defined in `namespace` are printed as their name.
+	/// Other numerals are printed as numbers if `zero` and `succ` are defined, so `add 1 2` reduces to `3` instead of `[a b] a (a (a b))`.
+	pub fn pretty_named(self: &'e Expr<'e>, namespace: &NamespaceMut<'e>, notation: Notation, width: usize) -> Result<String, LambdaError> {
+		// Only the first definition of a name is found when parsing, so later ones are skipped
+		let mut names: HashMap<ByAddress<'e, Expr<'e>>, &'e str> = HashMap::new();
+		let mut seen = HashSet::new();
+		namespace.for_each(|name| {
+			if !seen.insert(name.string.as_str()) { return }
+			if let NamedObject::Expr(expr) = name.object {
+				names.entry(ByAddress(expr)).or_insert(name.string.as_str());
+			}
+		});
+		let find = |string: &str| namespace.find(|name| name.string == string).and_then(|name| match name.object {
+			NamedObject::Expr(expr) => Some(expr),
+			NamedObject::Namespace(_) => None,
+		});
+		let numerals = find("zero").zip(find("succ"));
+		let folded: HashSet<&str> = names.values().copied().collect();
+		self.pretty_folded(notation, width, |expr| {
+			names.get(&ByAddress(expr)).map(|name| name.to_string())
+				.or_else(|| numerals.and_then(|(zero, succ)| numeral(expr, zero, succ)).map(|n| n.to_string()))
+		}, |name| folded.contains(name))
+	}
+	/// Pretty print, with lambdas and applications that don't use variables from outside of them replaced by what `fold` returns for them.
+	/// Variables aren't given names that are `taken`, so they can't be confused with folded subterms.
+	fn pretty_folded(self: &'e Expr<'e>, notation: Notation, width: usize, fold: impl Fn(&'e Expr<'e>) -> Option<String>, taken: impl Fn(&str) -> bool) -> Result<String, LambdaError> {
+		let trees = &LinkArena::new();
+		// Name of the variable bound at every level
+		let mut binders: Vec<String> = Vec::new();
+		let mut unused = (1..).map(var_name).filter(|name| !taken(name));
+		let mut nodes = Vec::new();
+		let mut tasks: Vec<(_, &BindSubTree, _)> = vec![(self, BindTree::NONE, 0)];
+		while let Some((expr, mut tree, depth)) = tasks.pop() {
+			if let (Expr::Lambda { .. } | Expr::Application { .. }, BindTree::None) = (expr, tree) {
+				if let Some(name) = fold(expr) {
+					nodes.push(Node::Atom(name));
+					continue
+				}
+			}
+			nodes.push(match expr {
+				Expr::Variable => match tree {
+					BindTree::End(level) => Node::Atom(binders[*level - 1].clone()),
+					BindTree::None => Node::Atom(FREE_NAME.to_owned()),
+					BindTree::Branch(..) => return Err(LambdaError::UnexpectedBranchInSubstitution),
+				}
+				Expr::Lambda { bind, expr } => {
+					tree.push_binding(trees, depth + 1, bind)?;
+					tasks.push((expr, tree, depth + 1));
+					if binders.len() == depth {
+						binders.push(unused.next().unwrap());
+					}
+					Node::Lambda(binders[depth].clone())
+				}
+				Expr::Application { func, args } => {
+					let (func_tree, args_tree) = tree.split()?;
+					tasks.push((args, args_tree, depth));
+					tasks.push((func, func_tree, depth));
+					Node::App
+				}
+			});
+		}
+		Ok(layout(&nodes, notation, width))
+	}
+}
+
+#[test]
+fn pretty_test() {
+	use crate::parse::parse;
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let pretty = |string, notation, width| parse(string, namespace, exprs).unwrap().pretty(notation, width).unwrap();
+
+	assert_eq!(pretty("[x y z] x z (y z)", Notation::Brackets, 80), "[a b c] a c (b c)");
+	assert_eq!(pretty("[x y z] x z (y z)", Notation::Lambda, 80), "λa b c. a c (b c)");
+	assert_eq!(pretty("([x] x x) ([x] x x)", Notation::Brackets, 80), "([a] a a) ([a] a a)");
+	assert_eq!(pretty("[f] ([x] f (x x)) ([x] f (x x))", Notation::Lambda, 80), "λa. (λb. a (b b)) (λb. a (b b))");
+	assert_eq!(pretty("[x] y x", Notation::Brackets, 80), "[a] free a");
+	assert_eq!(pretty("[f x] f ([y] y) x", Notation::Backslash, 80), "\\a b. a (\\c. c) b");
+	assert_eq!(pretty("[f x] f ([y] y) x", Notation::Fun, 80), "fun a b -> a (fun c -> c) b");
+	assert_eq!(pretty("[f x] f ([y] y) x", Notation::Latex, 0), "\\lambda a\\,b.\\, a\\ (\\lambda c.\\, c)\\ b");
+	assert_eq!(pretty("[f x] f x ([y] y) (f (f x))", Notation::Brackets, 16), "[a b] a\n  b\n  ([c] c)\n  (a (a b))");
+	assert_eq!(pretty("[f x] f (f x x x x x x x) x", Notation::Brackets, 20), "[a b] a\n  (a b b b b b b b)\n  b");
+	assert_eq!(pretty("[f x] f (f x x x x x x x x x x x x) x", Notation::Brackets, 20), "[a b] a\n  (a\n    b\n    b\n    b\n    b\n    b\n    b\n    b\n    b\n    b\n    b\n    b\n    b)\n  b");
+
+	// Output parses back to the same expression, also when wrapped
+	for string in [
+		"[f] ([x] f (x x)) ([x] f (x x))",
+		"[n f x] f (n f x)",
+		"[m n] n ([n f x] n ([g h] h (g f)) ([u] x) ([u] u)) m",
+		"[a b c d e f g h i j k l m n o p q r s t u v w x y z a1 b1] b1 a1 (z y) ([u] u z a)",
+		"[x] y x ([z] z y)",
+	] {
+		let expr = parse(string, namespace, exprs).unwrap();
+		for notation in [Notation::Brackets, Notation::Lambda, Notation::Backslash, Notation::Fun] {
+			for width in [0, 10, 80] {
+				let printed = expr.pretty(notation, width).unwrap();
+				assert_eq!(parse(&printed, namespace, exprs).unwrap(), expr, "{printed}");
+			}
+		}
+	}
+
+	// Folding subterms back into names
+	for (name, string) in [
+		("zero", "[f x] x"), ("succ", "[n f x] f (n f x)"), ("add", "[m n f x] m f (n f x)"),
+		("true", "[x y] x"), ("id", "[x] x"), ("two", "[f x] f (f x)"),
+	] {
+		namespace.add(name, parse(string, namespace, exprs).unwrap(), exprs);
+	}
+	let named = |string, reduce: bool| {
+		let expr = parse(string, namespace, exprs).unwrap();
+		let expr = if reduce { expr.reduce(exprs).unwrap() } else { expr };
+		expr.pretty_named(namespace, Notation::Brackets, 80).unwrap()
+	};
+	assert_eq!(named("add 1 2", true), "3");
+	assert_eq!(named("add 1 2", false), "add 1 2");
+	assert_eq!(named("[p] p true (succ 4) ([x] x)", false), "[a] a true 5 id");
+	// Names are preferred over numerals
+	assert_eq!(named("add 1 1", true), "two");
+	assert_eq!(named("[p] p 0 (succ 1)", false), "[a] a zero 2");
+	// Subterms using variables from outside of them aren't folded
+	assert_eq!(named("[f x y] x", false), "[a] true");
+	assert_eq!(named("[f x] f ([y] x)", false), "[a b] a ([c] b)");
+	// Variables are named around the names subterms are folded into
+	for (name, string) in [("a", "[x y z] z"), ("x", "[x y z] y")] {
+		namespace.add(name, parse(string, namespace, exprs).unwrap(), exprs);
+	}
+	assert_eq!(named("[p] p ([x y z] z)", false), "[b] b a");
+	let string = "[a b c d e f g h i j k l m n o p q r s t u v w x y z] z a ([x y z] y) ([x y z] z)";
+	let printed = named(string, false);
+	assert!(printed.starts_with("[b c d e f g h i j k l m n o p q r s t u v w y z a1 b1] b1 b x a"), "{printed}");
+	assert_eq!(parse(&printed, namespace, exprs).unwrap(), parse(string, namespace, exprs).unwrap());
+}
@@ -1,12 +1,351 @@
 //! This file contains functions to evaluate or beta reduce expressions
 
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Deserialize, Serialize};
+
 use hashdb::{LinkArena, TypeStore};
 
-use super::{BindIndex, BindSubTree, Expr, LambdaError};
+use super::{decode::canonical_nat, BindIndex, BindSubTree, BindTree, Binding, ByAddress, Expr, Jets, LambdaError, ReduceCache};
+
+/// A single step taken when descending from an `Expr` into one of its subexpressions
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes, Debug))]
+pub enum PathStep {
+	/// Into the body of a `Lambda`
+	Body,
+	/// Into the function of an `Application`
+	Func,
+	/// Into the arguments of an `Application`
+	Args,
+}
+
+/// Location of a subexpression, as the list of steps taken from the root expression
+pub type ExprPath = Vec<PathStep>;
+
+/// Order in which redexes are contracted and how far an expression is reduced.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Default)]
+pub enum ReductionStrategy {
+	/// Leftmost-outermost first, arguments are substituted unreduced. Finds the normal form if there is one.
+	#[default]
+	NormalOrder,
+	/// Leftmost-innermost first, arguments are reduced to normal form before being substituted.
+	ApplicativeOrder,
+	/// Arguments are substituted unreduced, never reduces inside of lambdas.
+	CallByName,
+	/// Arguments are reduced before being substituted, never reduces inside of lambdas.
+	CallByValue,
+	/// Stops once the expression is of the form `[x y ..] v a b ..`, arguments are left unreduced.
+	HeadNormalForm,
+	/// Stops once the expression is a lambda or of the form `v a b ..`, arguments are left unreduced.
+	WeakHeadNormalForm,
+}
+impl ReductionStrategy {
+	pub const ALL: [ReductionStrategy; 6] = [
+		Self::NormalOrder, Self::ApplicativeOrder, Self::CallByName, Self::CallByValue, Self::HeadNormalForm, Self::WeakHeadNormalForm,
+	];
+	/// Short name, used when selecting a strategy in the cli
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::NormalOrder => "normal",
+			Self::ApplicativeOrder => "applicative",
+			Self::CallByName => "cbn",
+			Self::CallByValue => "cbv",
+			Self::HeadNormalForm => "hnf",
+			Self::WeakHeadNormalForm => "whnf",
+		}
+	}
+	/// Whether lambda bodies are reduced
+	fn reduces_under_lambda(self) -> bool {
+		matches!(self, Self::NormalOrder | Self::ApplicativeOrder | Self::HeadNormalForm)
+	}
+	/// Whether arguments are reduced before being substituted
+	fn is_strict(self) -> bool {
+		matches!(self, Self::ApplicativeOrder | Self::CallByValue)
+	}
+	/// Whether the function and arguments of an application that can't be contracted are reduced
+	fn reduces_arguments(self) -> bool {
+		!matches!(self, Self::HeadNormalForm | Self::WeakHeadNormalForm)
+	}
+	/// Strategy used to reduce the function of an application before attempting to contract it
+	fn head_strategy(self) -> Self {
+		match self {
+			Self::ApplicativeOrder | Self::CallByValue => self,
+			_ => Self::WeakHeadNormalForm,
+		}
+	}
+}
+impl fmt::Display for ReductionStrategy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+impl FromStr for ReductionStrategy {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL.into_iter().find(|strategy| strategy.name() == s).ok_or_else(|| {
+			let names = Self::ALL.map(Self::name).join(", ");
+			format!("unknown reduction strategy `{s}`, expected one of: {names}")
+		})
+	}
+}
+
+/// Evaluator used to reduce expressions
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Default)]
+pub enum Engine {
+	/// Substitution of arguments into lambda bodies, following a `ReductionStrategy`
+	#[default]
+	Substitution,
+	/// Interaction net based optimal reduction, see `Expr::reduce_optimal`
+	Optimal,
+	/// Call-by-need reduction sharing evaluated arguments, see `Expr::reduce_lazy`
+	Lazy,
+	/// Substitution that reduces independent arguments on multiple threads, see `Expr::reduce_parallel`
+	Parallel,
+	/// Krivine machine running a compiled form of the expression, see `Expr::reduce_krivine`
+	Krivine,
+}
+impl Engine {
+	pub const ALL: [Engine; 5] = [Self::Substitution, Self::Optimal, Self::Lazy, Self::Parallel, Self::Krivine];
+	/// Short name, used when selecting an engine in the cli
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Substitution => "substitution",
+			Self::Optimal => "optimal",
+			Self::Lazy => "lazy",
+			Self::Parallel => "parallel",
+			Self::Krivine => "krivine",
+		}
+	}
+}
+impl fmt::Display for Engine {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+impl FromStr for Engine {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL.into_iter().find(|engine| engine.name() == s).ok_or_else(|| {
+			let names = Self::ALL.map(Self::name).join(", ");
+			format!("unknown engine `{s}`, expected one of: {names}")
+		})
+	}
+}
+
+/// Limits and options used when reducing an expression
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ReduceConfig {
+	/// Strategy used to pick which redexes to contract
+	pub strategy: ReductionStrategy,
+	/// Maximum number of beta reductions to perform, unlimited if None
+	pub fuel: Option<usize>,
+	/// Maximum number of nodes an expression produced by a beta reduction may have, unlimited if None
+	pub max_size: Option<usize>,
+	/// Maximum number of pending frames on the reduction work stack, roughly the nesting depth of the expression.
+	/// The stack is kept on the heap, so this is unlimited by default.
+	pub recursion_limit: usize,
+	/// Eta reduce the result once beta reduction finishes
+	pub eta: bool,
+	/// Collect `ReduceStats` while reducing, this measures the size of every expression produced by a beta reduction
+	pub stats: bool,
+}
+impl Default for ReduceConfig {
+	fn default() -> Self {
+		Self { strategy: ReductionStrategy::default(), fuel: Some(10_000), max_size: None, recursion_limit: usize::MAX, eta: false, stats: false }
+	}
+}
+
+/// Measurements of the work done by a reduction
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ReduceStats {
+	/// Number of beta reductions performed
+	pub steps: usize,
+	/// Number of nodes of the largest expression produced by a beta reduction
+	pub max_size: usize,
+	/// Largest number of lambdas a reduced subexpression was nested in
+	pub max_bind_depth: usize,
+	/// Number of variables replaced by arguments
+	pub substitutions: usize,
+	/// Number of distinct expression nodes built that weren't part of the reduced expression, this is how much the arena grows at most as it never frees them during a reduction
+	pub allocations: usize,
+}
+impl ReduceStats {
+	/// Combine with the measurements of another part of the same reduction
+	pub fn merge(&mut self, other: &ReduceStats) {
+		self.steps += other.steps;
+		self.max_size = self.max_size.max(other.max_size);
+		self.max_bind_depth = self.max_bind_depth.max(other.max_bind_depth);
+		self.substitutions += other.substitutions;
+		self.allocations += other.allocations;
+	}
+}
+impl fmt::Display for ReduceStats {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "beta reductions: {}", self.steps)?;
+		writeln!(f, "largest term: {} nodes", self.max_size)?;
+		writeln!(f, "deepest binding: {} lambdas", self.max_bind_depth)?;
+		writeln!(f, "substitutions: {}", self.substitutions)?;
+		writeln!(f, "allocations: {} nodes", self.allocations)
+	}
+}
 
-/// Recursively substitute expressions for certain variables
+/// Outcome of reducing an expression with a `ReduceConfig`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Reduction<'e> {
+	/// Reduced expression, only partially reduced if `out_of_fuel` is set
+	pub expr: &'e Expr<'e>,
+	/// Number of beta reductions performed
+	pub steps: usize,
+	/// Set if reduction stopped early because fuel ran out
+	pub out_of_fuel: bool,
+	/// Measurements of the reduction, if `ReduceConfig::stats` was set
+	pub stats: Option<ReduceStats>,
+}
+impl<'e> Reduction<'e> {
+	/// Returns the reduced expression, or an error if the reduction didn't finish
+	pub fn finished(self) -> Result<&'e Expr<'e>, LambdaError> {
+		if self.out_of_fuel { Err(LambdaError::OutOfFuel(self.steps)) }
+		else { Ok(self.expr) }
+	}
+}
+
+/// Jetted applications nested deeper than this inside of the arguments of other jetted applications are left to beta reduction
+const MAX_JET_DEPTH: usize = 64;
+
+/// Keeps track of fuel, normal form cache and jets during a reduction
+pub(super) struct ReduceState<'c, 'e> {
+	config: &'c ReduceConfig,
+	cache: Option<&'c ReduceCache<'e>>,
+	/// Only used by strategies that reduce to normal form, others would return more reduced expressions than without jets
+	jets: Option<&'c Jets<'e>>,
+	/// Number of jetted applications whose arguments are being reduced
+	jet_depth: usize,
+	pub(super) steps: usize,
+	pub(super) out_of_fuel: bool,
+	/// Only reported if `ReduceConfig::stats` is set, but always kept up to date except for `max_size` and `allocations`
+	pub(super) stats: ReduceStats,
+	/// Expression nodes built so far, only recorded if `ReduceConfig::stats` is set
+	allocated: HashSet<ByAddress<'e, Expr<'e>>>,
+	/// Called with the number of beta reductions every so many of them, reduction stops as if out of fuel once it returns false
+	interrupt: Option<(usize, &'c dyn Fn(usize) -> bool)>,
+}
+impl<'c, 'e> ReduceState<'c, 'e> {
+	pub(super) fn new(config: &'c ReduceConfig, cache: Option<&'c ReduceCache<'e>>, jets: Option<&'c Jets<'e>>) -> Self {
+		let jets = jets.filter(|_| config.strategy.reduces_under_lambda() && config.strategy.reduces_arguments());
+		Self { config, cache, jets, jet_depth: 0, steps: 0, out_of_fuel: false, stats: ReduceStats::default(), allocated: HashSet::new(), interrupt: None }
+	}
+	/// Record expression node built by the reduction
+	fn allocated(&mut self, expr: &'e Expr<'e>) -> &'e Expr<'e> {
+		if self.config.stats {
+			self.allocated.insert(ByAddress(expr));
+		}
+		expr
+	}
+	/// Measurements so far, counting the nodes built that aren't part of `input` as allocations
+	pub(super) fn stats(&self, input: &'e Expr<'e>) -> ReduceStats {
+		let mut allocated = self.allocated.clone();
+		let mut stack = vec![input];
+		while let Some(expr) = stack.pop() {
+			allocated.remove(&ByAddress(expr));
+			match expr {
+				Expr::Variable => {}
+				Expr::Lambda { expr, .. } => stack.push(expr),
+				Expr::Application { func, args } => stack.extend([*args, *func]),
+			}
+		}
+		ReduceStats { allocations: allocated.len(), ..self.stats }
+	}
+	/// Uses up fuel for one beta reduction, returns false (and records it) if there is none left
+	fn consume_fuel(&mut self) -> bool {
+		let interrupted = self.interrupt.is_some_and(|(interval, proceed)| self.steps.is_multiple_of(interval) && !proceed(self.steps));
+		if !interrupted && self.config.fuel.is_none_or(|fuel| self.steps < fuel) {
+			self.steps += 1;
+			self.stats.steps += 1;
+			true
+		} else {
+			self.out_of_fuel = true;
+			false
+		}
+	}
+}
+
+/// Work left to do in `recur_replace` once a subexpression has been replaced
+enum ReplaceTask<'e, 'r> {
+	Replace(&'e Expr<'e>, BindIndex<'r>),
+	Lambda(&'e Binding<'e>),
+	Application,
+}
+
+/// Work left to do in `partial_reduce` once a subexpression has been reduced
+enum ReduceFrame<'e, 'r> {
+	/// Re-wrap reduced lambda body
+	Lambda,
+	/// Function of application was reduced using the head strategy, contract if it is a lambda
+	Func { args: &'e Expr<'e>, args_bind: BindIndex<'r>, strategy: ReductionStrategy },
+	/// Arguments were reduced before substitution, contract with the already-reduced function
+	StrictArgs { bind: &'e Binding<'e>, body: &'e Expr<'e>, func_bind: BindIndex<'r>, strategy: ReductionStrategy },
+	/// Function of an application that couldn't be contracted was reduced, reduce arguments next
+	StuckFunc { args: &'e Expr<'e>, args_bind: BindIndex<'r>, strategy: ReductionStrategy },
+	/// Arguments of an application that couldn't be contracted were reduced, re-wrap application
+	StuckArgs { func: &'e Expr<'e>, func_bind: BindIndex<'r> },
+	/// Record reduced expression as the normal form of the original expression
+	Cache(&'e Expr<'e>),
+}
+
+/// What `partial_reduce` does next
+enum ReduceControl<'e, 'r> {
+	/// Reduce an expression, `entry` is false when continuing with the result of a beta reduction
+	Reduce { expr: &'e Expr<'e>, bind_index: BindIndex<'r>, strategy: ReductionStrategy, entry: bool },
+	/// Pass reduced expression to the top frame of the stack
+	Return(&'e Expr<'e>, BindIndex<'r>),
+}
+
+/// Brent's cycle detection on the expressions that a subexpression is successively contracted to
+struct CycleDetector<'e, 'r> {
+	saved: (&'e Expr<'e>, &'r BindSubTree<'r>),
+	power: usize,
+	length: usize,
+}
+impl<'e, 'r> CycleDetector<'e, 'r> {
+	fn new(expr: &'e Expr<'e>, tree: &'r BindSubTree<'r>) -> Self {
+		Self { saved: (expr, tree), power: 1, length: 0 }
+	}
+	/// Record the next expression, returns the length of the cycle once the sequence repeats.
+	/// Expressions and trees are hash-consed, so only their addresses (or values, for leaves that may be constants) are compared.
+	fn step(&mut self, expr: &'e Expr<'e>, tree: &'r BindSubTree<'r>) -> Option<usize> {
+		self.length += 1;
+		let same_expr = std::ptr::eq(self.saved.0, expr) || matches!((self.saved.0, expr), (Expr::Variable, Expr::Variable));
+		let same_tree = std::ptr::eq(self.saved.1, tree) || match (self.saved.1, tree) {
+			(BindTree::None, BindTree::None) => true,
+			(BindTree::End(saved), BindTree::End(level)) => saved == level,
+			_ => false,
+		};
+		if same_expr && same_tree {
+			return Some(self.length);
+		}
+		if self.length == self.power {
+			self.saved = (expr, tree);
+			self.power *= 2;
+			self.length = 0;
+		}
+		None
+	}
+}
+/// Record result of contracting the subexpression at `depth`, errors if it has been contracted to the same expression before
+fn check_cycle<'e, 'r>(cycles: &mut [Option<CycleDetector<'e, 'r>>], depth: usize, expr: &'e Expr<'e>, tree: &'r BindSubTree<'r>) -> Result<(), LambdaError> {
+	match cycles.get_mut(depth).and_then(Option::as_mut).and_then(|cycle| cycle.step(expr, tree)) {
+		Some(length) => Err(LambdaError::Diverges(length)),
+		None => Ok(()),
+	}
+}
+
+/// Substitute expressions for certain variables
 /// Takes lambda expression, for each variable in Lambda { expr }, if Lambda { tree } index == replace_index, replace subexpr with replacement and subtree with replacement_tree
+/// Uses an explicit stack so that deeply nested expressions don't overflow.
 impl<'e> Expr<'e> {
+	#[allow(clippy::too_many_arguments)]
 	fn recur_replace<'r>(
 		self: &'e Expr<'e>,					// Working Expression
 		bind_index: &mut BindIndex<'r>,	// Replace index in ReplaceTree to replace
@@ -14,94 +353,563 @@ impl<'e> Expr<'e> {
 		replace_bind: &'r BindSubTree<'r>,	// Bind Tree to replace with
 		binds: &'r impl TypeStore<'r>,		// BindSubTree Arena
 		exprs: &'e impl TypeStore<'e>,		// Expr Arena
+		stats: &mut ReduceStats,			// Substitutions are counted here
+		mut allocated: Option<&mut HashSet<ByAddress<'e, Expr<'e>>>>,	// Built nodes are recorded here
 	) -> Result<&'e Expr<'e>, LambdaError> {
-		Ok(match self {
-			Expr::Variable => {
-				// When encounter a variable and index is correct, replace with replacement
-				// Must be BindTree::None because replace_in_expr's variables aren't registered in external_tree
-				match bind_index.tree {
-					BindSubTree::Branch(_, _) => Err(LambdaError::UnexpectedBranchInSubstitution)?,
-					BindSubTree::End(val) if *val == bind_index.index => {
-						bind_index.tree = replace_bind;
-						replace_expr
-					}
-					_ => &self,
+		let mut tasks = vec![ReplaceTask::Replace(self, bind_index.clone())];
+		let mut replaced: Vec<(&'e Expr<'e>, BindIndex<'r>)> = Vec::new();
+		while let Some(task) = tasks.pop() {
+			match task {
+				// Nothing to replace if no variables are bound
+				ReplaceTask::Replace(expr, bind_index) if matches!(bind_index.tree, BindTree::None) => replaced.push((expr, bind_index)),
+				ReplaceTask::Replace(expr, bind_index) => match expr {
+					Expr::Variable => {
+						// When encounter a variable and index is correct, replace with replacement
+						// Must be BindTree::None because replace_in_expr's variables aren't registered in external_tree
+						match bind_index.tree {
+							BindSubTree::Branch(_, _) => Err(LambdaError::UnexpectedBranchInSubstitution)?,
+							BindSubTree::End(val) if *val == bind_index.index => {
+								stats.substitutions += 1;
+								replaced.push((replace_expr, BindIndex::new(bind_index.index, replace_bind)))
+							}
+							_ => replaced.push((expr, bind_index)),
+						}
+					}
+					// When encounter a lambda, unwrap, replace, re-wrap
+					Expr::Lambda { bind, expr } => {
+						tasks.push(ReplaceTask::Lambda(bind));
+						tasks.push(ReplaceTask::Replace(expr, bind_index));
+					}
+					// When encounter an application, split into the function and substitution portions of the pointer tree to replace in
+					Expr::Application { func, args } => {
+						let (func_bind, args_bind) = bind_index.split()?;
+						tasks.push(ReplaceTask::Application);
+						tasks.push(ReplaceTask::Replace(args, args_bind));
+						tasks.push(ReplaceTask::Replace(func, func_bind));
+					}
+				}
+				ReplaceTask::Lambda(bind) => {
+					let (expr, bind_index) = replaced.pop().unwrap();
+					let expr = Expr::lambda(bind, expr, exprs);
+					if let Some(allocated) = &mut allocated { allocated.insert(ByAddress(expr)); }
+					replaced.push((expr, bind_index));
+				}
+				ReplaceTask::Application => {
+					let (args, args_bind) = replaced.pop().unwrap();
+					let (func, func_bind) = replaced.pop().unwrap();
+					let expr = Expr::app(func, args, exprs);
+					if let Some(allocated) = &mut allocated { allocated.insert(ByAddress(expr)); }
+					replaced.push((expr, BindIndex::join(func_bind, args_bind, binds)));
 				}
 			}
-			// When encounter a lambda, unwrap, recurse, re-wrap
-			Expr::Lambda { bind, expr } => {
-				let replaced_expr = expr.recur_replace(bind_index, replace_expr, replace_bind, binds, exprs)?;
+		}
+		let (expr, replaced_bind) = replaced.pop().unwrap();
+		*bind_index = replaced_bind;
+		Ok(expr)
+	}
 	
-				Expr::lambda(bind, &replaced_expr, exprs)
+	/// Returns true if expression contains more than `limit` nodes, without visiting more than `limit` nodes
+	fn exceeds_size(&self, limit: usize) -> bool {
+		let mut remaining = limit;
+		let mut stack = vec![self];
+		while let Some(expr) = stack.pop() {
+			if remaining == 0 { return true }
+			remaining -= 1;
+			match expr {
+				Expr::Variable => {},
+				Expr::Lambda { expr, .. } => stack.push(expr),
+				Expr::Application { func, args } => { stack.push(args); stack.push(func); }
 			}
-			// When encounter an application in the replacement expression:
-			Expr::Application { func, args } => {
-				// Split into the function and substitution portions of the pointer tree to replace in
-				let (mut func_bind, mut args_bind) = bind_index.split()?;
-	
-				let func = func.recur_replace(&mut func_bind, replace_expr, replace_bind, binds, exprs)?;
-				let args = args.recur_replace(&mut args_bind, replace_expr, replace_bind, binds, exprs)?;
-	
-				*bind_index = BindIndex::join(func_bind, args_bind, binds);
-				Expr::app(&func, &args, exprs)
+		}
+		false
+	}
+
+	/// Number of nodes in expression
+	fn size(&self) -> usize {
+		let mut size = 0;
+		let mut stack = vec![self];
+		while let Some(expr) = stack.pop() {
+			size += 1;
+			match expr {
+				Expr::Variable => {},
+				Expr::Lambda { expr, .. } => stack.push(expr),
+				Expr::Application { func, args } => { stack.push(args); stack.push(func); }
 			}
-		})
+		}
+		size
 	}
-	
-	/// Reduces reducing_expr and returns &'e Expr<'e>
-	fn partial_reduce<'r>(
+
+	/// Contract a redex, returning the replaced body. `func_bind` is the BindIndex of the lambda and `args_bind` of the arguments.
+	#[allow(clippy::too_many_arguments)]
+	fn contract<'r>(
+		bind: &'e Binding<'e>,
+		body: &'e Expr<'e>,
+		func_bind: BindIndex<'r>,
+		args: &'e Expr<'e>,
+		args_bind: BindIndex<'r>,
+		state: &mut ReduceState<'_, 'e>,
+		reps: &'r impl TypeStore<'r>,
+		exprs: &'e impl TypeStore<'e>
+	) -> Result<(&'e Expr<'e>, BindIndex<'r>), LambdaError> {
+		// Replace all tree in expr
+		let mut bind_index = func_bind;
+		bind_index.push_binding(bind, reps)?;
+		state.stats.max_bind_depth = state.stats.max_bind_depth.max(bind_index.index);
+
+		let allocated = state.config.stats.then_some(&mut state.allocated);
+		let replaced_expr = body.recur_replace(&mut bind_index, args, args_bind.tree, reps, exprs, &mut state.stats, allocated)?;
+
+		bind_index.index -= 1; // All of current index will be replaced in recur_replace, thus this is needed
+
+		if state.config.stats {
+			let size = replaced_expr.size();
+			state.stats.max_size = state.stats.max_size.max(size);
+			if let Some(max_size) = state.config.max_size.filter(|&max_size| size > max_size) {
+				return Err(LambdaError::SizeLimitExceeded(max_size));
+			}
+		} else if let Some(max_size) = state.config.max_size {
+			if replaced_expr.exceeds_size(max_size) {
+				return Err(LambdaError::SizeLimitExceeded(max_size));
+			}
+		}
+		Ok((replaced_expr, bind_index))
+	}
+
+	/// Normal form of a jetted function applied to as many arguments as it takes, computed natively.
+	/// Arguments that aren't numerals yet are reduced first, if that fails or doesn't produce numerals the application is left to beta reduction.
+	/// The beta reductions spent on the arguments still count against the fuel, so failed attempts can't make a reduction do more work than its budget.
+	/// Only numerals in the form `[f x] f (f .. x)` are used, others like `[f] f` don't reduce to the same normal forms.
+	fn reduce_jet<'r>(
 		self: &'e Expr<'e>,
-		bind_index: &mut BindIndex<'r>,
-		depth: usize,
+		state: &mut ReduceState<'_, 'e>,
+		reps: &'r impl TypeStore<'r>,
+		exprs: &'e impl TypeStore<'e>
+	) -> Option<&'e Expr<'e>> {
+		let (jet, args) = state.jets?.saturated(self)?;
+		let out_of_fuel = state.out_of_fuel;
+		let mut nats = Vec::new();
+		for arg in args {
+			let nat = match canonical_nat(arg) {
+				None if state.jet_depth < MAX_JET_DEPTH => {
+					state.jet_depth += 1;
+					let reduced = arg.partial_reduce(&mut BindIndex::DEFAULT.clone(), state.config.strategy, state, reps, exprs);
+					state.jet_depth -= 1;
+					reduced.ok().filter(|_| !state.out_of_fuel).and_then(canonical_nat)
+				}
+				nat => nat,
+			};
+			nats.extend(nat);
+		}
+		let result = if nats.len() == jet.arity() { jet.evaluate(&nats, state.config.max_size, exprs) } else { None };
+		match result {
+			// Running out of fuel in an argument stops beta reduction of the application right away
+			None => state.out_of_fuel = out_of_fuel,
+			// Numerals are built natively, so their nodes are recorded here
+			Some(result) if state.config.stats => {
+				let mut stack = vec![result];
+				while let Some(expr) = stack.pop() {
+					if !state.allocated.insert(ByAddress(expr)) { continue }
+					match expr {
+						Expr::Variable => {}
+						Expr::Lambda { expr, .. } => stack.push(expr),
+						Expr::Application { func, args } => stack.extend([*args, *func]),
+					}
+				}
+			}
+			Some(_) => {}
+		}
+		result
+	}
+
+	/// Reduces reducing_expr according to `strategy` and returns &'e Expr<'e>.
+	/// Works through an explicit stack of `ReduceFrame`s instead of recursing, so deeply nested expressions can be reduced.
+	pub(super) fn partial_reduce<'r>(
+		self: &'e Expr<'e>,
+		bind_index_out: &mut BindIndex<'r>,
+		strategy: ReductionStrategy,
+		state: &mut ReduceState<'_, 'e>,
 		reps: &'r impl TypeStore<'r>,
 		exprs: &'e impl TypeStore<'e>
 	) -> Result<&'e Expr<'e>, LambdaError> {
-		if depth > 200 {
-			return Err(LambdaError::RecursionDepthExceeded);
+		let mut stack: Vec<ReduceFrame<'e, 'r>> = Vec::new();
+		// Contracting the same subexpression (at a given stack depth) back to an expression it already was means it loops forever
+		let mut cycles: Vec<Option<CycleDetector<'e, 'r>>> = Vec::new();
+		let mut control = ReduceControl::Reduce { expr: self, bind_index: bind_index_out.clone(), strategy, entry: true };
+		loop {
+			control = match control {
+				ReduceControl::Reduce { expr, mut bind_index, strategy, entry } => {
+					if stack.len() >= state.config.recursion_limit {
+						return Err(LambdaError::RecursionDepthExceeded);
+					}
+
+					// Jetted functions applied to numerals are computed instead of reduced
+					if matches!(expr, Expr::Application { .. }) && matches!(bind_index.tree, BindTree::None) {
+						if let Some(reduced) = expr.reduce_jet(state, reps, exprs) {
+							control = ReduceControl::Return(reduced, bind_index);
+							continue
+						}
+					}
+					// Normal forms of applications that don't contain variables bound outside of them can be cached
+					if let Some(cache) = state.cache.filter(|_| {
+						entry
+						&& strategy == ReductionStrategy::NormalOrder
+						&& matches!(expr, Expr::Application { .. })
+						&& matches!(bind_index.tree, BindTree::None)
+					}) {
+						if let Some(reduced) = cache.get(expr) {
+							control = ReduceControl::Return(reduced, bind_index);
+							continue
+						}
+						stack.push(ReduceFrame::Cache(expr));
+					}
+					if entry {
+						cycles.resize_with(cycles.len().max(stack.len() + 1), || None);
+						cycles[stack.len()] = Some(CycleDetector::new(expr, bind_index.tree));
+					}
+
+					match expr {
+						Expr::Variable => ReduceControl::Return(expr, bind_index),
+						Expr::Lambda { bind, expr } if strategy.reduces_under_lambda() => {
+							bind_index.push_binding(bind, reps)?;
+							state.stats.max_bind_depth = state.stats.max_bind_depth.max(bind_index.index);
+							stack.push(ReduceFrame::Lambda);
+							ReduceControl::Reduce { expr, bind_index, strategy, entry: true }
+						}
+						Expr::Lambda { .. } => ReduceControl::Return(expr, bind_index),
+						Expr::Application { func, args } => {
+							// Split subtrees & reduce function tree
+							let (func_bind, args_bind) = bind_index.split()?;
+							stack.push(ReduceFrame::Func { args, args_bind, strategy });
+							ReduceControl::Reduce { expr: func, bind_index: func_bind, strategy: strategy.head_strategy(), entry: true }
+						}
+					}
+				}
+				ReduceControl::Return(expr, mut bind_index) => match stack.pop() {
+					None => {
+						*bind_index_out = bind_index;
+						return Ok(expr);
+					}
+					Some(ReduceFrame::Lambda) => {
+						let bind = bind_index.pop_binding(reps, exprs)?;
+						ReduceControl::Return(state.allocated(Expr::lambda(bind, expr, exprs)), bind_index)
+					}
+					Some(ReduceFrame::Func { args, args_bind, strategy }) => match expr {
+						// Only contract if there is fuel left, otherwise leave application as-is
+						Expr::Lambda { bind, expr: body } if state.consume_fuel() => {
+							// Strict strategies reduce the arguments before substituting them
+							if strategy.is_strict() {
+								stack.push(ReduceFrame::StrictArgs { bind, body, func_bind: bind_index, strategy });
+								ReduceControl::Reduce { expr: args, bind_index: args_bind, strategy, entry: true }
+							} else {
+								let (expr, bind_index) = Expr::contract(bind, body, bind_index, args, args_bind, state, reps, exprs)?;
+								check_cycle(&mut cycles, stack.len(), expr, bind_index.tree)?;
+								ReduceControl::Reduce { expr, bind_index, strategy, entry: false }
+							}
+						}
+						// If Variable or unreduced Application, finish reducing function & substitution (if the strategy allows) & return unreduced Application and merge variable bindings.
+						_ if strategy.reduces_arguments() && strategy.head_strategy() != strategy => {
+							stack.push(ReduceFrame::StuckFunc { args, args_bind, strategy });
+							ReduceControl::Reduce { expr, bind_index, strategy, entry: true }
+						}
+						_ if strategy.reduces_arguments() => {
+							stack.push(ReduceFrame::StuckArgs { func: expr, func_bind: bind_index });
+							ReduceControl::Reduce { expr: args, bind_index: args_bind, strategy, entry: true }
+						}
+						_ => ReduceControl::Return(state.allocated(Expr::app(expr, args, exprs)), BindIndex::join(bind_index, args_bind, reps)),
+					}
+					Some(ReduceFrame::StrictArgs { bind, body, func_bind, strategy }) => {
+						let (expr, bind_index) = Expr::contract(bind, body, func_bind, expr, bind_index, state, reps, exprs)?;
+						check_cycle(&mut cycles, stack.len(), expr, bind_index.tree)?;
+						ReduceControl::Reduce { expr, bind_index, strategy, entry: false }
+					}
+					Some(ReduceFrame::StuckFunc { args, args_bind, strategy }) => {
+						stack.push(ReduceFrame::StuckArgs { func: expr, func_bind: bind_index });
+						ReduceControl::Reduce { expr: args, bind_index: args_bind, strategy, entry: true }
+					}
+					Some(ReduceFrame::StuckArgs { func, func_bind }) => {
+						ReduceControl::Return(state.allocated(Expr::app(func, expr, exprs)), BindIndex::join(func_bind, bind_index, reps))
+					}
+					Some(ReduceFrame::Cache(original)) => {
+						// Partially reduced expressions aren't normal forms
+						if let Some(cache) = state.cache.filter(|_| !state.out_of_fuel) {
+							cache.insert(original, expr);
+						}
+						ReduceControl::Return(expr, bind_index)
+					}
+				}
+			}
 		}
+	}
 	
+	/// Reduce expression to normal form using the default configuration, fails if it runs out of fuel
+	pub fn reduce(self: &'e Expr<'e>, exprs: &'e impl TypeStore<'e>) -> Result<&'e Expr<'e>, LambdaError> {
+		self.reduce_with(&ReduceConfig::default(), exprs)?.finished()
+	}
+
+	/// Reduce expression using a given configuration, returning the partially reduced expression if it runs out of fuel
+	pub fn reduce_with(self: &'e Expr<'e>, config: &ReduceConfig, exprs: &'e impl TypeStore<'e>) -> Result<Reduction<'e>, LambdaError> {
+		self.reduce_inner(&mut ReduceState::new(config, None, None), exprs)
+	}
+
+	/// Reduce expression like `reduce_with`, calling `proceed` with the number of beta reductions done so far every `interval` of them.
+	/// Reduction stops as if it ran out of fuel once `proceed` returns false, so long reductions can report progress and be cancelled.
+	pub fn reduce_interruptible(self: &'e Expr<'e>, config: &ReduceConfig, interval: usize, proceed: &dyn Fn(usize) -> bool, exprs: &'e impl TypeStore<'e>) -> Result<Reduction<'e>, LambdaError> {
+		let state = &mut ReduceState::new(config, None, None);
+		state.interrupt = Some((interval.max(1), proceed));
+		self.reduce_inner(state, exprs)
+	}
+
+	/// Reduce expression using a given configuration, reusing and recording normal forms in `cache`
+	pub fn reduce_cached(self: &'e Expr<'e>, config: &ReduceConfig, cache: &ReduceCache<'e>, exprs: &'e impl TypeStore<'e>) -> Result<Reduction<'e>, LambdaError> {
+		self.reduce_inner(&mut ReduceState::new(config, Some(cache), None), exprs)
+	}
+
+	/// Reduce expression like `reduce_cached`, computing jetted arithmetic on numerals natively.
+	/// Gives the same normal form as without jets, usually with fewer beta reductions, but more if jetted functions get arguments that don't reduce to numerals.
+	pub fn reduce_jetted(self: &'e Expr<'e>, config: &ReduceConfig, cache: &ReduceCache<'e>, jets: &Jets<'e>, exprs: &'e impl TypeStore<'e>) -> Result<Reduction<'e>, LambdaError> {
+		self.reduce_inner(&mut ReduceState::new(config, Some(cache), Some(jets)), exprs)
+	}
+
+	fn reduce_inner(self: &'e Expr<'e>, state: &mut ReduceState<'_, 'e>, exprs: &'e impl TypeStore<'e>) -> Result<Reduction<'e>, LambdaError> {
+		let reps = &LinkArena::new();
+		let config = state.config;
+		let mut expr = self.partial_reduce(&mut BindIndex::DEFAULT.clone(), config.strategy, state, reps, exprs)?;
+		if config.eta && !state.out_of_fuel {
+			expr = expr.eta_reduce(exprs)?;
+		}
+		Ok(Reduction { expr, steps: state.steps, out_of_fuel: state.out_of_fuel, stats: config.stats.then(|| state.stats(self)) })
+	}
+
+	/// Contracts the leftmost-outermost redex, pushing the steps taken to reach it onto `path`.
+	/// Returns None (leaving `bind_index` and `path` as they were) if there is no redex.
+	fn step_reduce<'r>(
+		self: &'e Expr<'e>,
+		bind_index: &mut BindIndex<'r>,
+		path: &mut ExprPath,
+		reps: &'r impl TypeStore<'r>,
+		exprs: &'e impl TypeStore<'e>
+	) -> Result<Option<&'e Expr<'e>>, LambdaError> {
 		Ok(match self {
-			Expr::Variable => self,
+			Expr::Variable => None,
 			Expr::Lambda { bind, expr } => {
 				bind_index.push_binding(bind, reps)?;
-	
-				let reduced_expr = expr.partial_reduce(bind_index, depth, reps, exprs)?;
-	
-				Expr::lambda(bind_index.pop_binding(reps, exprs)?, reduced_expr, exprs)
+				path.push(PathStep::Body);
+
+				let stepped_expr = expr.step_reduce(bind_index, path, reps, exprs)?;
+				let bind = bind_index.pop_binding(reps, exprs)?;
+
+				if stepped_expr.is_none() { path.pop(); }
+				stepped_expr.map(|expr| Expr::lambda(bind, expr, exprs))
+			}
+			// Application of a lambda is the outermost redex, contract it without reducing the result
+			Expr::Application { func: Expr::Lambda { bind, expr }, args } => {
+				let (func_bind, args_bind) = bind_index.split()?;
+				*bind_index = func_bind;
+				bind_index.push_binding(bind, reps)?;
+
+				let replaced_expr = expr.recur_replace(bind_index, args, args_bind.tree, reps, exprs, &mut ReduceStats::default(), None)?;
+
+				bind_index.index -= 1; // All of current index was replaced in recur_replace
+				Some(replaced_expr)
 			}
 			Expr::Application { func, args } => {
-				// Split subtrees
 				let (mut func_bind, mut args_bind) = bind_index.split()?;
-				// Reduce function tree
-				let func = func.partial_reduce(&mut func_bind, depth, reps, exprs)?;
-	
-				match func {
-					Expr::Lambda { bind, expr } => {
-						// Replace all tree in expr & reduce the output
-						*bind_index = func_bind;
-						bind_index.push_binding(bind, reps)?;
-	
-						let replaced_expr = expr.recur_replace(bind_index, &args, &args_bind.tree, reps, exprs)?;
-	
-						bind_index.index -= 1; // All of current index will be replaced in recur_replace, thus this is needed
-	
-						let depth = depth + 1;
-						replaced_expr.partial_reduce(bind_index, depth, reps, exprs)?
-					}
-					_ => {
-						// If Variable or unreduced Application, reduce substitution & return unreduced Application and merge variable bindings.
-						let args = args.partial_reduce(&mut args_bind, depth, reps, exprs)?;
-						*bind_index = BindIndex::join(func_bind, args_bind, reps);
-	
-						Expr::app(func, args, exprs)
-					}
+
+				// Try leftmost (function) side first, then arguments
+				path.push(PathStep::Func);
+				let stepped = if let Some(func) = func.step_reduce(&mut func_bind, path, reps, exprs)? {
+					Some(Expr::app(func, args, exprs))
+				} else {
+					path.pop();
+					path.push(PathStep::Args);
+					let stepped_args = args.step_reduce(&mut args_bind, path, reps, exprs)?;
+					if stepped_args.is_none() { path.pop(); }
+					stepped_args.map(|args| Expr::app(func, args, exprs))
+				};
+
+				if stepped.is_some() {
+					*bind_index = BindIndex::join(func_bind, args_bind, reps);
 				}
+				stepped
 			}
 		})
 	}
-	
-	pub fn reduce(self: &'e Expr<'e>, exprs: &'e impl TypeStore<'e>) -> Result<&'e Expr<'e>, LambdaError> {
+
+	/// Performs exactly one normal-order (leftmost-outermost) beta reduction.
+	/// Returns the new expression along with the path to the contracted redex, or None if the expression is already in normal form.
+	pub fn reduce_step(self: &'e Expr<'e>, exprs: &'e impl TypeStore<'e>) -> Result<Option<(&'e Expr<'e>, ExprPath)>, LambdaError> {
 		let reps = &LinkArena::new();
-		Ok(self.partial_reduce(&mut BindIndex::DEFAULT.clone(), 0, reps, exprs)?)
+		let mut path = ExprPath::new();
+		let stepped = self.step_reduce(&mut BindIndex::DEFAULT.clone(), &mut path, reps, exprs)?;
+		Ok(stepped.map(|expr| (expr, path)))
 	}
 }
+
+#[test]
+fn reduce_step_test() {
+	use crate::{name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let expr = parse("([x y] x) ([x] x) ([x] x)", namespace, exprs).unwrap();
+
+	// `([x y] x) ([x] x)` is contracted first, as it is the leftmost redex
+	let (expr, path) = expr.reduce_step(exprs).unwrap().unwrap();
+	assert_eq!(path, vec![PathStep::Func]);
+	assert_eq!(expr, parse("([y x] x) ([x] x)", namespace, exprs).unwrap());
+
+	let (expr, path) = expr.reduce_step(exprs).unwrap().unwrap();
+	assert_eq!(path, vec![]);
+	assert_eq!(expr, parse("[x] x", namespace, exprs).unwrap());
+
+	assert!(expr.reduce_step(exprs).unwrap().is_none());
+
+	// Redexes under lambdas and in arguments are found too
+	let expr = parse("[z] z (([x] x) z)", namespace, exprs).unwrap();
+	let (expr, path) = expr.reduce_step(exprs).unwrap().unwrap();
+	assert_eq!(path, vec![PathStep::Body, PathStep::Args]);
+	assert_eq!(expr, parse("[z] z z", namespace, exprs).unwrap());
+	assert_eq!(expr, expr.reduce(exprs).unwrap());
+}
+
+#[test]
+fn reduction_strategy_test() {
+	use crate::{name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let reduce = |string, strategy| {
+		let config = &ReduceConfig { strategy, ..Default::default() };
+		parse(string, namespace, exprs).unwrap().reduce_with(config, exprs).and_then(Reduction::finished)
+	};
+	let parsed = |string| parse(string, namespace, exprs).unwrap();
+
+	// Only lazy strategies terminate when the argument has no normal form
+	let omega = "([x] y) (([x] x x) ([x] x x))";
+	for strategy in ReductionStrategy::ALL {
+		match strategy {
+			ReductionStrategy::ApplicativeOrder | ReductionStrategy::CallByValue => assert!(reduce(omega, strategy).is_err()),
+			_ => assert_eq!(reduce(omega, strategy).unwrap(), Expr::VAR),
+		}
+	}
+
+	// Weak strategies don't reduce inside lambdas
+	let lambda = "[x] ([y] y) x";
+	assert_eq!(reduce(lambda, ReductionStrategy::NormalOrder).unwrap(), parsed("[x] x"));
+	assert_eq!(reduce(lambda, ReductionStrategy::HeadNormalForm).unwrap(), parsed("[x] x"));
+	assert_eq!(reduce(lambda, ReductionStrategy::CallByName).unwrap(), parsed(lambda));
+	assert_eq!(reduce(lambda, ReductionStrategy::WeakHeadNormalForm).unwrap(), parsed(lambda));
+
+	// Head normal forms don't reduce arguments
+	let stuck = "[z] z (([y] y) z)";
+	assert_eq!(reduce(stuck, ReductionStrategy::NormalOrder).unwrap(), parsed("[z] z z"));
+	assert_eq!(reduce(stuck, ReductionStrategy::HeadNormalForm).unwrap(), parsed(stuck));
+	let stuck = "z (([y] y) z)";
+	assert_eq!(reduce(stuck, ReductionStrategy::CallByValue).unwrap(), parsed("z z"));
+	assert_eq!(reduce(stuck, ReductionStrategy::WeakHeadNormalForm).unwrap(), parsed(stuck));
+
+	assert_eq!("cbv".parse::<ReductionStrategy>(), Ok(ReductionStrategy::CallByValue));
+	assert!("lazy".parse::<ReductionStrategy>().is_err());
+}
+
+#[test]
+fn reduce_fuel_test() {
+	use crate::{name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let expr = parse("([x] x) (([x] x) (([x] x) ([x] x)))", namespace, exprs).unwrap();
+
+	// Reduction stops with a partially reduced expression that can be continued from
+	let config = &ReduceConfig { fuel: Some(2), ..Default::default() };
+	let reduction = expr.reduce_with(config, exprs).unwrap();
+	assert_eq!(reduction, Reduction { expr: parse("([x] x) ([x] x)", namespace, exprs).unwrap(), steps: 2, out_of_fuel: true, stats: None });
+	assert!(matches!(reduction.finished(), Err(LambdaError::OutOfFuel(2))));
+
+	let reduction = reduction.expr.reduce_with(config, exprs).unwrap();
+	assert_eq!(reduction, Reduction { expr: parse("[x] x", namespace, exprs).unwrap(), steps: 1, out_of_fuel: false, stats: None });
+
+	// Interrupted reductions stop the same way
+	let steps = std::cell::RefCell::new(Vec::new());
+	let proceed = |done| { steps.borrow_mut().push(done); done < 2 };
+	assert_eq!(expr.reduce_interruptible(&ReduceConfig::default(), 2, &proceed, exprs).unwrap(), expr.reduce_with(config, exprs).unwrap());
+	assert_eq!(steps.borrow()[..2], [0, 2]);
+
+	// Expressions that keep growing never repeat, so they only stop once fuel runs out
+	let growing = parse("([x] x x x) ([x] x x x)", namespace, exprs).unwrap();
+	assert!(matches!(growing.reduce_with(config, exprs).and_then(Reduction::finished), Err(LambdaError::OutOfFuel(2))));
+
+	let config = &ReduceConfig { max_size: Some(10), ..Default::default() };
+	let expr = parse("([x] x x x x) ([x] x x x x)", namespace, exprs).unwrap();
+	assert!(matches!(expr.reduce_with(config, exprs), Err(LambdaError::SizeLimitExceeded(10))));
+
+	let config = &ReduceConfig { recursion_limit: 3, ..Default::default() };
+	let expr = parse("[a] [b] [c] [d] ([x] x) d", namespace, exprs).unwrap();
+	assert!(matches!(expr.reduce_with(config, exprs), Err(LambdaError::RecursionDepthExceeded)));
+}
+
+#[test]
+fn diverges_test() {
+	use crate::{name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let omega = parse("([x] x x) ([x] x x)", namespace, exprs).unwrap();
+	assert!(matches!(omega.reduce(exprs), Err(LambdaError::Diverges(1))));
+
+	// Contracts to `([y] y) (w w)` and then back to `w w`
+	let expr = parse("([x] ([y] y) (x x)) ([x] ([y] y) (x x))", namespace, exprs).unwrap();
+	assert!(matches!(expr.reduce(exprs), Err(LambdaError::Diverges(2))));
+
+	// Loops only if the argument is reduced
+	let expr = parse("([x y] y) (([x] x x) ([x] x x))", namespace, exprs).unwrap();
+	assert_eq!(expr.reduce(exprs).unwrap(), parse("[y] y", namespace, exprs).unwrap());
+	let config = &ReduceConfig { strategy: ReductionStrategy::ApplicativeOrder, ..Default::default() };
+	assert!(matches!(expr.reduce_with(config, exprs), Err(LambdaError::Diverges(1))));
+}
+
+#[test]
+fn deep_reduce_test() {
+	// `[z] z (z (z ... (([x] x) z)))`, nested far deeper than the native stack would allow with recursion
+	let exprs = &LinkArena::new();
+	let depth = 50_000;
+	let id = Expr::lambda(Binding::END, Expr::VAR, exprs);
+	let (mut body, mut bind) = (Expr::app(id, Expr::VAR, exprs), Binding::right(Binding::END, exprs));
+	for _ in 0..depth {
+		body = Expr::app(Expr::VAR, body, exprs);
+		bind = Binding::branch(Binding::END, bind, exprs);
+	}
+	let expr = Expr::lambda(bind, body, exprs);
+
+	let reduction = expr.reduce_with(&ReduceConfig::default(), exprs).unwrap();
+	assert_eq!(reduction.steps, 1);
+	let Expr::Lambda { expr: mut body, .. } = reduction.expr else { panic!("expected lambda") };
+	for _ in 0..depth {
+		let Expr::Application { func: Expr::Variable, args } = body else { panic!("expected application") };
+		body = args;
+	}
+	assert!(matches!(body, Expr::Variable));
+}
+
+#[test]
+fn reduce_stats_test() {
+	use crate::{name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let expr = parse("([x] x x) ([y] y)", namespace, exprs).unwrap();
+	assert_eq!(expr.reduce_with(&ReduceConfig::default(), exprs).unwrap().stats, None);
+
+	// `([y] y) ([y] y)` replaces two variables and allocates the application, `[y] y` replaces one
+	let reduction = expr.reduce_with(&ReduceConfig { stats: true, ..Default::default() }, exprs).unwrap();
+	assert_eq!(reduction.stats, Some(ReduceStats { steps: 2, max_size: 5, max_bind_depth: 1, substitutions: 3, allocations: 1 }));
+
+	// `([y] y b) a` and the two lambdas of `[a b] a b` are built, `a b` is already part of the expression as `x a`
+	let expr = parse("[a b] ([x] x a) ([y] y b)", namespace, exprs).unwrap();
+	let stats = expr.reduce_with(&ReduceConfig { stats: true, ..Default::default() }, exprs).unwrap().stats.unwrap();
+	assert_eq!((stats.steps, stats.max_bind_depth, stats.allocations), (2, 3, 3));
+
+	// Rebuilding a normal form doesn't grow the arena
+	let expr = parse("[a b] a ([x] x) b", namespace, exprs).unwrap();
+	assert_eq!(expr.reduce_with(&ReduceConfig { stats: true, ..Default::default() }, exprs).unwrap().stats.unwrap().allocations, 0);
+}
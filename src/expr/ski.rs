@@ -0,0 +1,394 @@
+//! Compiles expressions to combinators with bracket abstraction, and reduces combinator terms
+
+use std::{collections::HashMap, fmt};
+
+use hashdb::{LinkArena, TypeStore};
+
+use super::{BindSubTree, BindTree, DeBruijn, Expr, LambdaError, ReduceConfig};
+
+/// Combinator of combinatory logic
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub enum Combinator {
+	/// `S x y z = x z (y z)`
+	S,
+	/// `K x y = x`
+	K,
+	/// `I x = x`
+	I,
+	/// `B x y z = x (y z)`
+	B,
+	/// `C x y z = x z y`
+	C,
+	/// `W x y = x y y`
+	W,
+}
+impl Combinator {
+	pub const ALL: [Combinator; 6] = [Self::S, Self::K, Self::I, Self::B, Self::C, Self::W];
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::S => "S",
+			Self::K => "K",
+			Self::I => "I",
+			Self::B => "B",
+			Self::C => "C",
+			Self::W => "W",
+		}
+	}
+	/// Number of arguments it needs to reduce
+	pub fn arity(self) -> usize {
+		match self {
+			Self::I => 1,
+			Self::K | Self::W => 2,
+			Self::S | Self::B | Self::C => 3,
+		}
+	}
+	/// Definition as a lambda term
+	fn definition(self) -> Result<DeBruijn, LambdaError> {
+		let (var, lambda, app) = (DeBruijn::var, DeBruijn::lambda, DeBruijn::app);
+		Ok(match self {
+			Self::S => lambda(lambda(lambda(app(app(var(3)?, var(1)?), app(var(2)?, var(1)?))))),
+			Self::K => lambda(lambda(var(2)?)),
+			Self::I => lambda(var(1)?),
+			Self::B => lambda(lambda(lambda(app(var(3)?, app(var(2)?, var(1)?))))),
+			Self::C => lambda(lambda(lambda(app(app(var(3)?, var(1)?), var(2)?)))),
+			Self::W => lambda(lambda(app(app(var(2)?, var(1)?), var(1)?))),
+		})
+	}
+}
+impl fmt::Display for Combinator {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+enum SkiNode {
+	Comb(Combinator),
+	/// Variable bound by the lambda at a level, only there while compiling
+	Var(usize),
+	/// Variable not bound by any lambda
+	Free,
+	App(usize, usize),
+}
+
+/// Term made of combinators, stored as nodes referring to each other by index so equal subterms are only stored once
+#[derive(Clone, Debug)]
+pub struct SkiTerm {
+	nodes: Vec<SkiNode>,
+	ids: HashMap<SkiNode, usize>,
+	root: usize,
+}
+impl SkiTerm {
+	fn new() -> Self {
+		Self { nodes: Vec::new(), ids: HashMap::new(), root: 0 }
+	}
+	fn add(&mut self, node: SkiNode) -> usize {
+		let nodes = &mut self.nodes;
+		*self.ids.entry(node).or_insert_with(|| {
+			nodes.push(node);
+			nodes.len() - 1
+		})
+	}
+	fn app(&mut self, func: usize, args: usize) -> usize {
+		self.add(SkiNode::App(func, args))
+	}
+	/// Add node, keeping track of the deepest variable in every node in `levels`
+	fn add_leveled(&mut self, levels: &mut Vec<usize>, node: SkiNode) -> usize {
+		let id = self.add(node);
+		if id == levels.len() {
+			levels.push(match node {
+				SkiNode::Var(level) => level,
+				SkiNode::App(func, args) => levels[func].max(levels[args]),
+				SkiNode::Comb(_) | SkiNode::Free => 0,
+			});
+		}
+		id
+	}
+	/// `comb func args`
+	fn add_comb(&mut self, levels: &mut Vec<usize>, comb: Combinator, func: usize, args: usize) -> usize {
+		let comb = self.add_leveled(levels, SkiNode::Comb(comb));
+		let func = self.add_leveled(levels, SkiNode::App(comb, func));
+		self.add_leveled(levels, SkiNode::App(func, args))
+	}
+	/// Bracket abstraction `[x] node` of the variable bound at `level`, which no variable in `node` is bound deeper than.
+	/// `[x] M x` becomes `M` only if `eta` is set.
+	fn abstract_var(&mut self, levels: &mut Vec<usize>, level: usize, node: usize, bcw: bool, eta: bool) -> usize {
+		if levels[node] < level {
+			let k = self.add_leveled(levels, SkiNode::Comb(Combinator::K));
+			return self.add_leveled(levels, SkiNode::App(k, node));
+		}
+		enum Task {
+			Visit(usize),
+			Build(usize),
+		}
+		let mut abstracted: HashMap<usize, usize> = HashMap::new();
+		let mut tasks = vec![Task::Visit(node)];
+		while let Some(task) = tasks.pop() {
+			match task {
+				Task::Visit(node) if abstracted.contains_key(&node) => {}
+				Task::Visit(node) => match self.nodes[node] {
+					SkiNode::App(func, args) => {
+						tasks.push(Task::Build(node));
+						tasks.extend([args, func].into_iter().filter(|child| levels[*child] == level).map(Task::Visit));
+					}
+					// The variable itself
+					_ => { abstracted.insert(node, self.add_leveled(levels, SkiNode::Comb(Combinator::I))); }
+				}
+				Task::Build(node) => {
+					let SkiNode::App(func, args) = self.nodes[node] else { unreachable!() };
+					let is_var = self.nodes[args] == SkiNode::Var(level);
+					let result = match (levels[func] == level, levels[args] == level) {
+						// `[x] M x = M`
+						(false, _) if is_var && eta => func,
+						(false, _) if bcw => self.add_comb(levels, Combinator::B, func, abstracted[&args]),
+						(false, _) => {
+							let k = self.add_leveled(levels, SkiNode::Comb(Combinator::K));
+							let func = self.add_leveled(levels, SkiNode::App(k, func));
+							self.add_comb(levels, Combinator::S, func, abstracted[&args])
+						}
+						(true, false) if bcw => self.add_comb(levels, Combinator::C, abstracted[&func], args),
+						(true, false) => {
+							let k = self.add_leveled(levels, SkiNode::Comb(Combinator::K));
+							let args = self.add_leveled(levels, SkiNode::App(k, args));
+							self.add_comb(levels, Combinator::S, abstracted[&func], args)
+						}
+						(true, true) if bcw && is_var => {
+							let w = self.add_leveled(levels, SkiNode::Comb(Combinator::W));
+							self.add_leveled(levels, SkiNode::App(w, abstracted[&func]))
+						}
+						(true, true) => self.add_comb(levels, Combinator::S, abstracted[&func], abstracted[&args]),
+					};
+					abstracted.insert(node, result);
+				}
+			}
+		}
+		abstracted[&node]
+	}
+
+	/// Reduce leftmost outermost redexes until the term is in normal form or `config.fuel` combinator reductions are done.
+	/// Fails if the term grows past `config.max_size` nodes.
+	pub fn reduce(&self, config: &ReduceConfig) -> Result<SkiReduction, LambdaError> {
+		enum Task {
+			Eval(usize),
+			/// Apply head to the last reduced arguments
+			Rebuild(usize, usize),
+		}
+		let mut term = self.clone();
+		let mut steps = 0;
+		let mut out_of_fuel = false;
+		let mut reduced = Vec::new();
+		let mut tasks = vec![Task::Eval(term.root)];
+		while let Some(task) = tasks.pop() {
+			match task {
+				Task::Eval(mut node) => loop {
+					let mut head = node;
+					let mut args = Vec::new();
+					while let SkiNode::App(func, arg) = term.nodes[head] {
+						args.push(arg);
+						head = func;
+					}
+					args.reverse();
+					match term.nodes[head] {
+						SkiNode::Comb(comb) if args.len() >= comb.arity() && !out_of_fuel => {
+							if config.fuel.is_some_and(|fuel| steps >= fuel) {
+								out_of_fuel = true;
+								continue
+							}
+							steps += 1;
+							let rest = args.split_off(comb.arity());
+							let contracted = match (comb, args.as_slice()) {
+								(Combinator::S, &[x, y, z]) => {
+									let (xz, yz) = (term.app(x, z), term.app(y, z));
+									term.app(xz, yz)
+								}
+								(Combinator::K, &[x, _]) => x,
+								(Combinator::I, &[x]) => x,
+								(Combinator::B, &[x, y, z]) => {
+									let yz = term.app(y, z);
+									term.app(x, yz)
+								}
+								(Combinator::C, &[x, y, z]) => {
+									let xz = term.app(x, z);
+									term.app(xz, y)
+								}
+								(Combinator::W, &[x, y]) => {
+									let xy = term.app(x, y);
+									term.app(xy, y)
+								}
+								_ => unreachable!(),
+							};
+							node = rest.into_iter().fold(contracted, |func, arg| term.app(func, arg));
+							if let Some(max_size) = config.max_size.filter(|&max_size| term.nodes.len() > max_size) {
+								return Err(LambdaError::SizeLimitExceeded(max_size));
+							}
+						}
+						_ => {
+							tasks.push(Task::Rebuild(head, args.len()));
+							tasks.extend(args.into_iter().rev().map(Task::Eval));
+							break
+						}
+					}
+				}
+				Task::Rebuild(head, len) => {
+					let args = reduced.split_off(reduced.len() - len);
+					let node = args.into_iter().fold(head, |func, arg| term.app(func, arg));
+					reduced.push(node);
+				}
+			}
+		}
+		term.root = reduced.pop().unwrap();
+		Ok(SkiReduction { term, steps, out_of_fuel })
+	}
+
+	/// Expression with the combinators replaced by their definitions
+	pub fn to_expr<'e>(&self, exprs: &'e impl TypeStore<'e>) -> Result<&'e Expr<'e>, LambdaError> {
+		let mut combs = HashMap::new();
+		for comb in Combinator::ALL {
+			combs.insert(comb, comb.definition()?.to_expr(exprs)?);
+		}
+		let mut built: HashMap<usize, &'e Expr<'e>> = HashMap::new();
+		let mut tasks = vec![(self.root, false)];
+		while let Some((node, children_built)) = tasks.pop() {
+			if built.contains_key(&node) { continue }
+			let expr = match self.nodes[node] {
+				SkiNode::Comb(comb) => combs[&comb],
+				SkiNode::Var(_) | SkiNode::Free => Expr::VAR,
+				SkiNode::App(func, args) if children_built => Expr::app(built[&func], built[&args], exprs),
+				SkiNode::App(func, args) => {
+					tasks.extend([(node, true), (args, false), (func, false)]);
+					continue
+				}
+			};
+			built.insert(node, expr);
+		}
+		Ok(built[&self.root])
+	}
+}
+
+impl fmt::Display for SkiTerm {
+	/// Prints `S (K I) K`, application is left associative
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		enum Token {
+			Node(usize, bool),
+			Str(&'static str),
+		}
+		let mut tokens = vec![Token::Node(self.root, false)];
+		while let Some(token) = tokens.pop() {
+			match token {
+				Token::Str(string) => f.write_str(string)?,
+				Token::Node(node, is_args) => match self.nodes[node] {
+					SkiNode::Comb(comb) => write!(f, "{comb}")?,
+					SkiNode::Var(_) | SkiNode::Free => f.write_str("free")?,
+					SkiNode::App(func, args) => {
+						if is_args {
+							f.write_str("(")?;
+							tokens.push(Token::Str(")"));
+						}
+						tokens.extend([Token::Node(args, true), Token::Str(" "), Token::Node(func, false)]);
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Outcome of reducing a combinator term with `SkiTerm::reduce`
+#[derive(Clone, Debug)]
+pub struct SkiReduction {
+	/// Normal form, or the partially reduced term if it ran out of fuel
+	pub term: SkiTerm,
+	/// Number of combinator reductions performed
+	pub steps: usize,
+	pub out_of_fuel: bool,
+}
+
+impl<'e> Expr<'e> {
+	/// Compile to S, K and I combinators, and also B, C and W if `bcw` is set, using bracket abstraction.
+	/// If `eta` is set `[x] M x` is compiled to `M`, giving smaller terms that are only equivalent up to eta conversion.
+	pub fn to_ski(self: &'e Expr<'e>, bcw: bool, eta: bool) -> Result<SkiTerm, LambdaError> {
+		enum Task<'e, 't> {
+			Compile(&'e Expr<'e>, &'t BindSubTree<'t>, usize),
+			Lambda(usize),
+			App,
+		}
+		let trees = &LinkArena::new();
+		let mut term = SkiTerm::new();
+		// Deepest variable in each node
+		let mut levels = Vec::new();
+		let mut compiled = Vec::new();
+		let mut tasks = vec![Task::Compile(self, BindTree::NONE, 0)];
+		while let Some(task) = tasks.pop() {
+			let node = match task {
+				Task::Compile(Expr::Variable, tree, _) => match tree {
+					BindTree::End(level) => SkiNode::Var(*level),
+					BindTree::None => SkiNode::Free,
+					BindTree::Branch(..) => return Err(LambdaError::UnexpectedBranchInSubstitution),
+				}
+				Task::Compile(Expr::Lambda { bind, expr }, mut tree, depth) => {
+					tree.push_binding(trees, depth + 1, bind)?;
+					tasks.push(Task::Lambda(depth + 1));
+					tasks.push(Task::Compile(expr, tree, depth + 1));
+					continue
+				}
+				Task::Compile(Expr::Application { func, args }, tree, depth) => {
+					let (func_tree, args_tree) = tree.split()?;
+					tasks.push(Task::App);
+					tasks.push(Task::Compile(args, args_tree, depth));
+					tasks.push(Task::Compile(func, func_tree, depth));
+					continue
+				}
+				Task::Lambda(level) => {
+					let body = compiled.pop().unwrap();
+					compiled.push(term.abstract_var(&mut levels, level, body, bcw, eta));
+					continue
+				}
+				Task::App => {
+					let args = compiled.pop().unwrap();
+					SkiNode::App(compiled.pop().unwrap(), args)
+				}
+			};
+			compiled.push(term.add_leveled(&mut levels, node));
+		}
+		term.root = compiled.pop().unwrap();
+		Ok(term)
+	}
+}
+
+#[test]
+fn ski_test() {
+	use crate::{name::church_namespace, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &church_namespace(exprs);
+	let ski = |string, bcw| parse(string, namespace, exprs).unwrap().to_ski(bcw, true).unwrap().to_string();
+
+	assert_eq!(ski("[x] x", false), "I");
+	assert_eq!(ski("[x y] x", false), "K");
+	assert_eq!(ski("[x y] y x", false), "S (K (S I)) K");
+	assert_eq!(ski("[x y] y x", true), "C I");
+	assert_eq!(ski("[f x] f (f x)", false), "S (S (K S) K) I");
+	assert_eq!(ski("[f x] f (f x)", true), "W B");
+	assert_eq!(ski("[x y z] x z (y z)", false), "S");
+	assert_eq!(ski("[x] y x", false), "free");
+	assert_eq!(ski("[x] x x", false), "S I I");
+	assert_eq!(parse("[x] y x", namespace, exprs).unwrap().to_ski(false, false).unwrap().to_string(), "S (K free) I");
+
+	// Reducing combinators gives the same normal forms as beta reduction, up to eta conversion
+	let config = &ReduceConfig { eta: true, fuel: Some(100_000), ..Default::default() };
+	for string in ["add 2 3", "mul 2 3", "[n] add n 1", "([x] x x) ([f x] f (f x))", "[x y] y ([z] z x)"] {
+		let expr = parse(string, namespace, exprs).unwrap();
+		let normal_form = expr.reduce_with(config, exprs).unwrap().finished().unwrap();
+		for bcw in [false, true] {
+			let reduction = expr.to_ski(bcw, true).unwrap().reduce(config).unwrap();
+			assert!(!reduction.out_of_fuel);
+			let back = reduction.term.to_expr(exprs).unwrap().reduce_with(config, exprs).unwrap().finished().unwrap();
+			assert_eq!(back, normal_form, "{string}");
+		}
+	}
+	let omega = parse("([x] x x) ([x] x x)", namespace, exprs).unwrap().to_ski(false, true).unwrap();
+	let reduction = omega.reduce(&ReduceConfig { fuel: Some(100), ..Default::default() }).unwrap();
+	assert!(reduction.out_of_fuel);
+	assert_eq!(reduction.steps, 100);
+	assert!(matches!(omega.reduce(&ReduceConfig { max_size: Some(20), ..Default::default() }), Err(LambdaError::SizeLimitExceeded(20))));
+}
@@ -0,0 +1,156 @@
+//! Recording of every step of a reduction, so it can be stored and replayed later
+
+use std::fmt;
+
+use bytecheck::CheckBytes;
+use rkyv::{with::Map, Archive, Deserialize, Serialize};
+
+use hashdb::{ArchiveDeserializer, ArchiveStore, HashType, LinkArena, TypeStore};
+
+use super::{BlcError, DeBruijn, Expr, ExprPath, LambdaError, PathStep, ReduceConfig};
+
+/// A single beta reduction in a `ReductionTrace`
+#[derive(Clone, Hash, PartialEq, Eq, Debug, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+#[archive(bound(serialize = "__S: ArchiveStore", deserialize = "__D: ArchiveDeserializer<'e>"))]
+pub struct TraceStep<'e> {
+	/// Number of this step, starting at 1
+	pub step: usize,
+	/// Location of the contracted redex in the previous expression
+	pub redex: ExprPath,
+	/// Expression after the redex was contracted
+	#[with(HashType)]
+	#[omit_bounds]
+	pub expr: &'e Expr<'e>,
+}
+
+/// Every intermediate expression of a normal order reduction
+#[derive(Clone, Hash, PartialEq, Eq, Debug, Archive, Serialize, Deserialize)]
+#[archive_attr(derive(CheckBytes))]
+#[archive(bound(serialize = "__S: ArchiveStore", deserialize = "__D: ArchiveDeserializer<'e>"))]
+pub struct ReductionTrace<'e> {
+	/// Expression before any reductions
+	#[with(HashType)]
+	#[omit_bounds]
+	pub start: &'e Expr<'e>,
+	#[with(Map<HashType>)]
+	#[omit_bounds]
+	pub steps: Vec<&'e TraceStep<'e>>,
+	/// Set if the trace stopped early because fuel ran out
+	pub out_of_fuel: bool,
+}
+impl<'e> ReductionTrace<'e> {
+	/// Last expression in the trace
+	pub fn result(&self) -> &'e Expr<'e> {
+		self.steps.last().map_or(self.start, |step| step.expr)
+	}
+	/// Expression after `step` reductions
+	pub fn expr_at(&self, step: usize) -> Option<&'e Expr<'e>> {
+		if step == 0 { Some(self.start) } else { self.steps.get(step - 1).map(|step| step.expr) }
+	}
+	/// Write trace as lines of binary lambda calculus bit strings: the start expression, then the redex path (`b`ody, `f`unc, `a`rgs, `.` for the root) and result of every step.
+	/// Ends with an `out of fuel` line if the reduction didn't finish.
+	pub fn to_bit_lines(&self) -> Result<String, BlcError> {
+		let mut lines = self.start.to_debruijn()?.to_bit_string();
+		lines.push('\n');
+		for step in &self.steps {
+			if step.redex.is_empty() { lines.push('.') }
+			lines.extend(step.redex.iter().map(|step| match step {
+				PathStep::Body => 'b',
+				PathStep::Func => 'f',
+				PathStep::Args => 'a',
+			}));
+			lines.push(' ');
+			lines.push_str(&step.expr.to_debruijn()?.to_bit_string());
+			lines.push('\n');
+		}
+		if self.out_of_fuel { lines.push_str("out of fuel\n") }
+		Ok(lines)
+	}
+	/// Read trace written by `to_bit_lines`
+	pub fn from_bit_lines(lines: &str, exprs: &'e LinkArena<'e>) -> Result<&'e ReductionTrace<'e>, BlcError> {
+		let mut lines = lines.lines().filter(|line| !line.trim().is_empty());
+		let start = DeBruijn::from_bit_string(lines.next().ok_or(BlcError::UnexpectedEnd)?)?.to_expr(exprs)?;
+		let mut steps = Vec::new();
+		let mut out_of_fuel = false;
+		for line in lines {
+			if line.trim() == "out of fuel" { out_of_fuel = true; continue }
+			let (path, expr) = line.split_once(' ').ok_or(BlcError::UnexpectedEnd)?;
+			let redex = path.chars().filter(|&c| c != '.').map(|c| match c {
+				'b' => Ok(PathStep::Body),
+				'f' => Ok(PathStep::Func),
+				'a' => Ok(PathStep::Args),
+				c => Err(BlcError::InvalidPathStep(c)),
+			}).collect::<Result<ExprPath, _>>()?;
+			let expr = DeBruijn::from_bit_string(expr)?.to_expr(exprs)?;
+			steps.push(exprs.add(TraceStep { step: steps.len() + 1, redex, expr }));
+		}
+		Ok(exprs.add(ReductionTrace { start, steps, out_of_fuel }))
+	}
+}
+impl<'e> fmt::Display for ReductionTrace<'e> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "0: {}", self.start)?;
+		for step in &self.steps {
+			writeln!(f, "{} at {:?}: {}", step.step, step.redex, step.expr)?;
+		}
+		if self.out_of_fuel {
+			writeln!(f, "ran out of fuel after {} steps", self.steps.len())?;
+		}
+		Ok(())
+	}
+}
+
+impl<'e> Expr<'e> {
+	/// Reduce expression in normal order, one step at a time, recording every intermediate expression.
+	/// Uses `fuel` from `config` to limit the number of steps.
+	pub fn reduce_traced(self: &'e Expr<'e>, config: &ReduceConfig, exprs: &'e LinkArena<'e>) -> Result<&'e ReductionTrace<'e>, LambdaError> {
+		let mut steps = Vec::new();
+		let mut current = self;
+		let out_of_fuel = loop {
+			if config.fuel.is_some_and(|fuel| steps.len() >= fuel) {
+				break current.reduce_step(exprs)?.is_some();
+			}
+			match current.reduce_step(exprs)? {
+				Some((expr, redex)) => {
+					steps.push(exprs.add(TraceStep { step: steps.len() + 1, redex, expr }));
+					current = expr;
+				}
+				None => break false,
+			}
+		};
+		Ok(exprs.add(ReductionTrace { start: self, steps, out_of_fuel }))
+	}
+}
+
+#[test]
+fn reduce_traced_test() {
+	use crate::{expr::PathStep, name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let expr = parse("([x y] x) ([x] x) ([x] x)", namespace, exprs).unwrap();
+
+	let trace = expr.reduce_traced(&ReduceConfig::default(), exprs).unwrap();
+	let redexes = trace.steps.iter().map(|step| (step.step, step.redex.clone())).collect::<Vec<_>>();
+	assert_eq!(redexes, vec![(1, vec![PathStep::Func]), (2, vec![])]);
+	assert_eq!(trace.expr_at(1), Some(parse("([y x] x) ([x] x)", namespace, exprs).unwrap()));
+	assert_eq!(trace.result(), expr.reduce(exprs).unwrap());
+	assert!(!trace.out_of_fuel);
+
+	let omega = parse("([x] x x) ([x] x x)", namespace, exprs).unwrap();
+	let trace = omega.reduce_traced(&ReduceConfig { fuel: Some(3), ..Default::default() }, exprs).unwrap();
+	assert_eq!(trace.steps.len(), 3);
+	assert!(trace.out_of_fuel);
+	assert_eq!(trace.result(), omega);
+
+	// Traces survive being written to text and read back, into the same or another arena
+	let trace = expr.reduce_traced(&ReduceConfig::default(), exprs).unwrap();
+	let lines = trace.to_bit_lines().unwrap();
+	assert_eq!(ReductionTrace::from_bit_lines(&lines, exprs).unwrap(), trace);
+	let other = &LinkArena::new();
+	assert_eq!(ReductionTrace::from_bit_lines(&lines, other).unwrap(), trace);
+	let trace = omega.reduce_traced(&ReduceConfig { fuel: Some(3), ..Default::default() }, exprs).unwrap();
+	assert_eq!(ReductionTrace::from_bit_lines(&trace.to_bit_lines().unwrap(), other).unwrap(), trace);
+	assert!(ReductionTrace::from_bit_lines("0010\nx 0010", exprs).is_err());
+}
@@ -6,7 +6,7 @@ use bevy_prototype_lyon::prelude::*;
 use block::{BindEntityTree, ObjectData, Orientation, PartialForm, WrappedExpr};
 use block_to_expr::block_to_expr;
 use expr::{Binding, Expr, BindTree};
-use hashdb::{LinkArena, TypeStore};
+use hashdb::TypeStore;
 use mouseover::{BottomHover, HoverState, TopHover};
 use placing::place_expr;
 
@@ -16,6 +16,8 @@ mod name;
 mod block;
 mod placing;
 mod parse;
+mod reducing;
+mod transpile;
 mod ui;
 mod block_to_expr;
 
@@ -50,9 +52,11 @@ fn main() {
 		.add_system(mouseover::mouseover_system)
 		.add_system(state_change_detect)
 		.add_system(ui::button_system)
+		.add_system(reducing::reduction_progress_system)
     	.add_system(bevy::window::exit_on_window_close_system)
 		.add_system(exprs_forming_system).add_system(reform_system)
 		.init_resource::<GameState>()
+		.init_resource::<reducing::BackgroundReduction>()
 		.run();
 }
 
@@ -89,6 +93,8 @@ fn input_system(
 	mut other_objects: Query<(Entity, &mut ObjectData, &mut block::WrappedExpr, &HoverState)>,
 	mut expr_text: Query<&mut Text, (With<ui::ExpressionText>, Without<ui::ReducedText>)>,
 	mut red_expr_text: Query<&mut Text, (With<ui::ReducedText>, Without<ui::ExpressionText>)>,
+	mut reduction: ResMut<reducing::BackgroundReduction>,
+	pool: Res<bevy::tasks::AsyncComputeTaskPool>,
 ) {
 	if keyboard_input.just_pressed(KeyCode::F) {
 		place_expr(commands, &mut app_state, &mut state, WrappedExpr::LAMBDA);
@@ -104,16 +110,8 @@ fn input_system(
 			  match block_to_expr(&h_expr) {
 					Ok(expr) => {
 						text.sections[0].value = format!("{}", &expr);
-						let arena = LinkArena::new();
-						match expr.reduce(&arena) {
-							Ok(red) => {
-								red_text.sections[0].value = format!("{}", &red);
-							}
-							Err(_) => {
-								red_text.sections[0].value = "unreducable".into();
-							}
-						}
-						
+						red_text.sections[0].value = "reducing...".into();
+						reduction.start(expr, &pool);
 					},
 					Err(_) => {
 						text.sections[0].value = "malformed expression".into()
@@ -122,6 +120,9 @@ fn input_system(
 			  break
 			}
 		  }
+	} else if keyboard_input.just_pressed(KeyCode::Escape) && reduction.is_running() {
+		reduction.cancel();
+		red_expr_text.iter_mut().next().unwrap().sections[0].value = "reduction cancelled".into();
 	} /* else if mouse_input.clear_just_pressed(MouseButton::Left) {
 		app_state.push(AppState::WiringObject).unwrap();
 	} */
@@ -70,7 +70,7 @@ impl<'e> NamespaceMut<'e> {
 		self.namespace.borrow_mut().add(name, expr, exprs)
 	}
 	pub fn find<P: FnMut(&&&'e Name<'e>) -> bool>(&self, predicate: P) -> Option<&'e Name<'e>> {
-		self.namespace.borrow().items.iter().find(predicate).as_deref().map(|n|*n)
+		self.namespace.borrow().items.iter().find(predicate).copied()
 	}
 	pub fn for_each<F: FnMut(&&'e Name<'e>)>(&self, func: F) {
 		self.namespace.borrow().items.iter().for_each(func)
@@ -109,11 +109,25 @@ impl<'e> Namespace<'e> {
 impl<'e> fmt::Display for Namespace<'e> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for item in &self.items {
-			match item.object {
-				NamedObject::Expr(expr) => writeln!(f, "{expr}")?,
-				_ => {},
+			if let NamedObject::Expr(expr) = item.object {
+				writeln!(f, "{expr}")?
 			}
 		}
 		Ok(())
     }
 }
+
+/// Namespace with the encodings tests are written in: church numerals and arithmetic, booleans, church and Scott lists, pairs and fixpoint combinators
+#[cfg(test)]
+pub(crate) fn church_namespace<'e>(exprs: &'e LinkArena<'e>) -> NamespaceMut<'e> {
+	let namespace = NamespaceMut::new();
+	for (name, string) in [
+		("zero", "[f x] x"), ("succ", "[n f x] f (n f x)"), ("add", "[m n f x] m f (n f x)"), ("mul", "[m n f] m (n f)"), ("pow", "[m n] n m"),
+		("pred", "[n f x] n ([g h] h (g f)) ([u] x) ([u] u)"), ("iszero", "[n] n ([u] [x y] y) ([x y] x)"),
+		("nil", "[c n] n"), ("cons", "[h t c n] c h (t c n)"), ("snil", "[n c] n"), ("scons", "[h t n c] c h t"), ("pair", "[a b p] p a b"),
+		("Y", "[f] ([x] f (x x)) ([x] f (x x))"), ("Z", "[f] ([x] f ([v] x x v)) ([x] f ([v] x x v))"), ("omega", "([x] x x) ([x] x x)"),
+	] {
+		namespace.add(name, crate::parse::parse(string, &namespace, exprs).unwrap(), exprs);
+	}
+	namespace
+}
@@ -6,7 +6,7 @@ use ariadne::{Color, Label, Report, Fmt, ReportKind, Source};
 use chumsky::{prelude::*, text::keyword};
 use hashdb::{LinkArena, TypeStore};
 
-use crate::{expr::{BindSubTree, Expr}, name::{NamedObject, NamespaceMut}};
+use crate::{expr::{BindSubTree, DeBruijn, Engine, Expr, Notation, ReductionStrategy}, name::{NamedObject, NamespaceMut}, transpile::Target};
 
 // Represents active bound variables in the course of parsing an expression
 #[derive(Default, Debug)]
@@ -18,9 +18,9 @@ impl BindMap {
 	fn bind_index(&self, string: &String) -> Option<usize> {
 		self.map.borrow().iter().enumerate().rev().find(|(_, e)|*e == string).map(|val|val.0 + 1)
 	}
-	fn push_bind(&self, string: &String) -> usize {
+	fn push_bind(&self, string: &str) -> usize {
 		let mut map = self.map.borrow_mut();
-		map.push(string.clone());
+		map.push(string.to_owned());
 		map.len()
 	}
 	fn pop_bind(&self) -> usize {
@@ -52,7 +52,7 @@ fn parser<'e: 'b, 'b, B: TypeStore<'b>, E: TypeStore<'e>>(namespace: &'b Namespa
 			).try_map(|num, span| {
 				match (lookup_expr(namespace, "zero", exprs), lookup_expr(namespace, "succ", exprs)) {
 					(Some(zero), Some(succ)) => {
-						let expr = (0..num).into_iter().fold(zero, |acc, _|Expr::app(succ, acc, exprs));
+						let expr = (0..num).fold(zero, |acc, _|Expr::app(succ, acc, exprs));
 						Ok((expr, BindSubTree::NONE))
 					}
 					_ => Err(Simple::custom(span, "names `zero` and `succ` must be defined to use numbers"))
@@ -70,10 +70,14 @@ fn parser<'e: 'b, 'b, B: TypeStore<'b>, E: TypeStore<'e>>(namespace: &'b Namespa
     	.or(number)
 		.or(expr.clone().delimited_by(just('('), just(')')).padded());
 
+		// Lambda header in bracket syntax `[x y]`, or in the usual notations `\x y.`, `λx y.` and `fun x y ->`
+		let names = name_parser().repeated().at_least(1);
+		let header = names.clone().delimited_by(just('['), just(']'))
+			.or(one_of("λ\\").ignore_then(names.clone()).then_ignore(just('.')))
+			.or(keyword("fun").ignore_then(names).then_ignore(just("->")));
+
 		// Parse `[x y z] x y z` as `[x] ([y] ([z] x y z))`
-		let lambda = name_parser()
-    		.repeated().at_least(1)
-			.delimited_by(just('['), just(']'))
+		let lambda = header
 			.map(|symbols| {
 				symbols.iter().for_each(|string|{
 					bind_map.push_bind(string);
@@ -94,14 +98,14 @@ fn parser<'e: 'b, 'b, B: TypeStore<'b>, E: TypeStore<'e>>(namespace: &'b Namespa
 
 		// An expression can be a lambda: `[x y]` an application: `x y` or a standalone variable / symbol: `x`
 		lambda.or(application).or(atom).padded().labelled("expression")
-	}).then_ignore(end())
+	})
 }
 // Parse expression
 pub fn parse<'e>(string: &str, namespace: &NamespaceMut<'e>, exprs: &'e LinkArena<'e>) -> Result<&'e Expr<'e>, anyhow::Error> {
 	let binds = &LinkArena::new();
 	let bind_map = &BindMap::default();
 	{
-		let parsed = parser(namespace, exprs, binds, bind_map).parse(string);
+		let parsed = parser(namespace, exprs, binds, bind_map).then_ignore(end()).parse(string);
 		match parsed {
 			Ok((expr, _)) => Ok(expr),
 			Err(errors) => {
@@ -112,6 +116,43 @@ pub fn parse<'e>(string: &str, namespace: &NamespaceMut<'e>, exprs: &'e LinkAren
 	}
 }
 
+/// Parser for de Bruijn terms like `λ λ 2 (2 1)`, `\\` can be written instead of `λ`
+fn debruijn_parser() -> impl Parser<char, DeBruijn, Error = Simple<char>> {
+	recursive(|term: Recursive<char, DeBruijn, Simple<char>>| {
+		let index = text::int::<_, Simple<char>>(10).padded()
+			.try_map(|s, span| match s.parse::<usize>() {
+				Ok(index) => DeBruijn::var(index).map_err(|e| Simple::custom(span, format!("{}", e))),
+				Err(e) => Err(Simple::custom(span, format!("{}", e))),
+			}).labelled("index");
+
+		let atom = index.or(term.clone().delimited_by(just('('), just(')')).padded());
+
+		let lambda = one_of("λ\\").padded().ignore_then(term).map(DeBruijn::lambda).labelled("lambda");
+
+		// Parse `1 2 λ 1` as `(1 2) (λ 1)`, the lambda takes the rest of the term
+		let application = atom.clone()
+			.then(atom.repeated())
+			.foldl(DeBruijn::app)
+			.then(lambda.clone().or_not())
+			.map(|(func, lambda)| match lambda {
+				Some(lambda) => DeBruijn::app(func, lambda),
+				None => func,
+			}).labelled("application");
+
+		lambda.or(application).padded().labelled("term")
+	}).then_ignore(end())
+}
+// Parse de Bruijn term
+pub fn parse_debruijn(string: &str) -> Result<DeBruijn, anyhow::Error> {
+	match debruijn_parser().parse(string) {
+		Ok(term) => Ok(term),
+		Err(errors) => {
+			gen_report(errors).try_for_each(|report|report.print(Source::from(&string)))?;
+			Err(anyhow::anyhow!("Error"))
+		}
+	}
+}
+
 /// Generate cool errors with ariadne
 pub fn gen_report(errors: Vec<Simple<char>>) -> impl Iterator<Item = Report> {
 	// Taken from json.rs example on chumsky github
@@ -201,8 +242,34 @@ pub enum Command<'e> {
 	Clear,
 	/// List current namespace's names
 	List,
+	/// Set the reduction strategy used to evaluate expressions
+	Strategy(ReductionStrategy),
+	/// Set the engine used to evaluate expressions
+	Engine(Engine),
+	/// Set maximum number of beta reductions per evaluation
+	Fuel(usize),
+	/// Continue reducing the last expression that ran out of fuel
+	Continue,
+	/// Set whether evaluated expressions are eta reduced
+	Eta(bool),
+	/// Set whether reduction statistics are printed after every evaluation
+	Stats(bool),
+	/// Set whether arithmetic on numerals is computed natively
+	Jets(bool),
+	/// Set the notation results are printed in
+	Notation(Notation),
 	// Evaluate passed expression and store output in 
 	Reduce(&'e Expr<'e>),
+	/// Evaluate passed expression one step at a time, printing every step, or writing them as bit lines to a file with `trace expr > file`
+	Trace { expr: &'e Expr<'e>, file: Option<String> },
+	/// Print the binary lambda calculus encoding of an expression and its size in bits
+	Blc(&'e Expr<'e>),
+	/// Compile an expression to combinators and reduce them
+	Ski(&'e Expr<'e>),
+	/// Write a module in the target language evaluating a named expression to a file
+	Transpile { target: Target, name: String, file: String },
+	/// Write a Graphviz DOT graph of a named expression to a file, optionally with its binding trees, as `dot name "file"` or `dot name > file`
+	Dot { name: String, file: String, bindings: bool },
 }
 /// Parse commands
 pub fn command_parser<'e: 'b, 'b>(namespace: &'b NamespaceMut<'e>, exprs: &'e LinkArena<'e>, binds: &'b LinkArena<'b>, bind_map: &'b BindMap) -> impl Parser<char, Command<'e>, Error = Simple<char>> + 'b {
@@ -214,6 +281,11 @@ pub fn command_parser<'e: 'b, 'b>(namespace: &'b NamespaceMut<'e>, exprs: &'e Li
 		.collect::<String>()
     	.padded()
 		.labelled("filepath");
+	// Shell style `> file`, quoted or not
+	let redirect = just('>')
+		.padded()
+		.ignore_then(filepath.or(filter(|c: &char| !c.is_whitespace()).repeated().at_least(1).collect::<String>().padded()))
+		.labelled("redirection");
 
 
 	/* #[derive(Clone, Copy)]
@@ -237,15 +309,85 @@ pub fn command_parser<'e: 'b, 'b>(namespace: &'b NamespaceMut<'e>, exprs: &'e Li
 		.or(
 			keyword("list").to(Command::List)
 		)
+		.or(
+			keyword("strategy")
+				.ignore_then(text::ident().padded())
+				.try_map(|name: String, span| name.parse().map_err(|err: String| Simple::custom(span, err)))
+				.map(Command::Strategy)
+		)
+		.or(
+			keyword("engine")
+				.ignore_then(text::ident().padded())
+				.try_map(|name: String, span| name.parse().map_err(|err: String| Simple::custom(span, err)))
+				.map(Command::Engine)
+		)
+		.or(
+			keyword("fuel")
+				.ignore_then(text::int(10).padded())
+				.try_map(|s: String, span| s.parse::<usize>().map_err(|e| Simple::custom(span, format!("{}", e))))
+				.map(Command::Fuel)
+		)
+		.or(
+			keyword("continue").to(Command::Continue)
+		)
+		.or(
+			keyword("eta")
+				.ignore_then(keyword("on").to(true).or(keyword("off").to(false)).padded())
+				.map(Command::Eta)
+		)
+		.or(
+			keyword("stats")
+				.ignore_then(keyword("on").to(true).or(keyword("off").to(false)).padded())
+				.map(Command::Stats)
+		)
+		.or(
+			keyword("jets")
+				.ignore_then(keyword("on").to(true).or(keyword("off").to(false)).padded())
+				.map(Command::Jets)
+		)
+		.or(
+			keyword("notation")
+				.ignore_then(text::ident().padded())
+				.try_map(|name: String, span| name.parse().map_err(|err: String| Simple::custom(span, err)))
+				.map(Command::Notation)
+		)
     	.or(
 			keyword("load").ignore_then(filepath).map(|file|Command::Load { file })
 		)
 		.or(
 			keyword("save").ignore_then(filepath).map(|file|Command::Save { file, overwrite: false })
 		)
+		.or(
+			keyword("trace")
+				.ignore_then(expr.clone())
+				.then(redirect.or_not())
+				.map(|((expr, _), file)| Command::Trace { expr, file })
+		)
+		.or(
+			keyword("blc").ignore_then(expr.clone()).map(|(expr, _)|Command::Blc(expr))
+		)
+		.or(
+			keyword("ski").ignore_then(expr.clone()).map(|(expr, _)|Command::Ski(expr))
+		)
+		.or(
+			keyword("transpile")
+				.ignore_then(text::ident().padded())
+				.try_map(|name: String, span| name.parse().map_err(|err: String| Simple::custom(span, err)))
+				.then(text::ident().padded())
+				.then(filepath)
+				.map(|((target, name), file)| Command::Transpile { target, name, file })
+		)
+		.or(
+			keyword("dot")
+				.ignore_then(keyword("bindings").padded().or_not())
+				.then(text::ident().padded())
+				.then(filepath.or(redirect))
+				.map(|((bindings, name), file)| Command::Dot { name, file, bindings: bindings.is_some() })
+		)
 		.or(
 			expr.clone().map(|(expr, _)|Command::Reduce(expr))
 		)
+		.then_ignore(end())
 		.labelled("command")
 }
 
@@ -274,5 +416,46 @@ fn parse_test() {
 	namespace.add("iszero", iszero, exprs);
 
 	let test = parse_reduce("iszero ([x y] y)", namespace, exprs).unwrap();
-	assert_eq!(test, parse("[x y] x", namespace, exprs).unwrap())
-}
\ No newline at end of file
+	assert_eq!(test, parse("[x y] x", namespace, exprs).unwrap());
+
+	// Usual lambda notations
+	let church_two = parse("[f x] f (f x)", namespace, exprs).unwrap();
+	for string in ["\\f x. f (f x)", "λf.λx.f (f x)", "λf. \\x. f (f x)", "fun f x -> f (f x)", "fun f -> [x] f (f x)"] {
+		assert_eq!(parse(string, namespace, exprs).unwrap(), church_two, "{string}");
+	}
+	// `fun` is still a name outside of lambda headers
+	assert_eq!(parse("[fun] fun fun", namespace, exprs).unwrap(), parse("[x] x x", namespace, exprs).unwrap());
+}
+
+#[test]
+fn command_test() {
+	use hashdb::LinkArena;
+
+	let exprs = &LinkArena::new();
+	let binds = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	let bind_map = &BindMap::default();
+	let parser = command_parser(namespace, exprs, binds, bind_map);
+	let dot = |string: &str| match parser.parse(string) {
+		Ok(Command::Dot { name, file, bindings }) => Some((name, file, bindings)),
+		_ => None,
+	};
+
+	let expected = Some(("two".to_owned(), "two.dot".to_owned(), false));
+	assert_eq!(dot("dot two \"two.dot\""), expected);
+	assert_eq!(dot("dot two > two.dot"), expected);
+	assert_eq!(dot("dot two>two.dot "), expected);
+	assert_eq!(dot("dot bindings two > \"my graphs/two.dot\""), Some(("two".to_owned(), "my graphs/two.dot".to_owned(), true)));
+	assert_eq!(dot("dot two >"), None);
+	assert_eq!(dot("dot two two.dot"), None);
+
+	let trace = |string: &str| match parser.parse(string) {
+		Ok(Command::Trace { expr, file }) => Some((expr, file)),
+		_ => None,
+	};
+	let id = parse("[x] x", namespace, exprs).unwrap();
+	assert_eq!(trace("trace [x] x"), Some((id, None)));
+	assert_eq!(trace("trace ([x] x) > id.trace"), Some((id, Some("id.trace".to_owned()))));
+	assert_eq!(trace("trace [x] x > \"my traces/id\""), Some((id, Some("my traces/id".to_owned()))));
+	assert_eq!(trace("trace [x] x >"), None);
+}
@@ -0,0 +1,88 @@
+//! Reduces expressions on the async compute task pool, so heavy terms don't freeze the window
+
+use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex};
+
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool};
+use hashdb::LinkArena;
+
+use crate::{expr::{Expr, LambdaError, ReduceConfig, Reduction}, ui};
+
+/// Beta reductions done between progress updates and checks for cancellation
+const PROGRESS_INTERVAL: usize = 1_000;
+/// Beta reductions after which the reduction is given up
+const MAX_STEPS: usize = 1_000_000;
+
+/// State shared between the ECS and the reduction task
+#[derive(Default)]
+struct Progress {
+	steps: AtomicUsize,
+	cancelled: AtomicBool,
+	/// Text to show once the reduction is finished
+	result: Mutex<Option<String>>,
+}
+
+/// Reduction running in the background, at most one runs at a time
+#[derive(Default)]
+pub struct BackgroundReduction {
+	progress: Option<Arc<Progress>>,
+}
+impl BackgroundReduction {
+	/// Start reducing expression, cancelling the reduction that is already running
+	pub fn start(&mut self, expr: &'static Expr<'static>, pool: &AsyncComputeTaskPool) {
+		self.cancel();
+		let progress = Arc::new(Progress::default());
+		self.progress = Some(progress.clone());
+		pool.spawn(async move {
+			let text = reduce_with_progress(expr, &progress);
+			*progress.result.lock().unwrap() = Some(text);
+		}).detach();
+	}
+	/// Stop the running reduction, its task finishes at the next progress update
+	pub fn cancel(&mut self) {
+		if let Some(progress) = self.progress.take() {
+			progress.cancelled.store(true, Ordering::Relaxed);
+		}
+	}
+	pub fn is_running(&self) -> bool {
+		self.progress.is_some()
+	}
+}
+
+/// Reduce expression in a single reduction, so cycle detection keeps working across progress updates, stopping early if it is cancelled
+fn reduce_with_progress(expr: &'static Expr<'static>, progress: &Progress) -> String {
+	let exprs = &LinkArena::new();
+	let config = &ReduceConfig { fuel: Some(MAX_STEPS), ..Default::default() };
+	let proceed = |steps| {
+		progress.steps.store(steps, Ordering::Relaxed);
+		!progress.cancelled.load(Ordering::Relaxed)
+	};
+	match expr.reduce_interruptible(config, PROGRESS_INTERVAL, &proceed, exprs) {
+		_ if progress.cancelled.load(Ordering::Relaxed) => "reduction cancelled".into(),
+		Ok(Reduction { expr: red, out_of_fuel: false, .. }) => match red.decode_string() {
+			Some(decoded) => format!("{}\n= {decoded}", &red),
+			None => format!("{}", &red),
+		},
+		Ok(Reduction { expr: red, steps, .. }) => format!("ran out of fuel after {steps} steps: {}", &red),
+		Err(LambdaError::Diverges(_)) => "this term loops".into(),
+		Err(_) => "unreducable".into(),
+	}
+}
+
+/// Show progress of the background reduction in the sidebar, and its result once it is finished
+pub fn reduction_progress_system(
+	mut reduction: ResMut<BackgroundReduction>,
+	mut red_expr_text: Query<&mut Text, With<ui::ReducedText>>,
+) {
+	let Some(progress) = reduction.progress.clone() else { return };
+	let red_text: &mut Text = &mut red_expr_text.iter_mut().next().unwrap();
+	match progress.result.lock().unwrap().take() {
+		Some(text) => {
+			red_text.sections[0].value = text;
+			reduction.progress = None;
+		}
+		None => {
+			let steps = progress.steps.load(Ordering::Relaxed);
+			red_text.sections[0].value = format!("reducing... {steps} beta reductions so far, press Escape to cancel");
+		}
+	}
+}
@@ -0,0 +1,334 @@
+//! Generates Rust or JavaScript modules that evaluate named expressions as native closures
+
+use std::{fmt, fmt::Write, str::FromStr};
+
+use hashdb::LinkArena;
+use thiserror::Error;
+
+use crate::{expr::{BindSubTree, BindTree, BindTreeError, Binding, Expr}, name::{Name, NamedObject}};
+
+#[derive(Error, Debug)]
+pub enum TranspileError {
+	#[error("`{0}` is a namespace, only expressions can be transpiled")]
+	NotAnExpression(String),
+	#[error("expression contains a free variable, only closed expressions can be transpiled")]
+	FreeVariable,
+	#[error("found variable in expression but binding tree is branching")]
+	UnexpectedBranch,
+	#[error("bind error: {0}")]
+	BindError(#[from] BindTreeError),
+}
+
+/// Language to generate
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Default)]
+pub enum Target {
+	#[default]
+	Rust,
+	JavaScript,
+}
+impl Target {
+	pub const ALL: [Target; 2] = [Self::Rust, Self::JavaScript];
+	/// Short name, used when selecting a target in the cli
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Rust => "rust",
+			Self::JavaScript => "js",
+		}
+	}
+}
+impl fmt::Display for Target {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+impl FromStr for Target {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL.into_iter().find(|target| target.name() == s).ok_or_else(|| {
+			let names = Self::ALL.map(Self::name).join(", ");
+			format!("unknown target `{s}`, expected one of: {names}")
+		})
+	}
+}
+
+/// Runtime shared by all generated Rust modules. Arguments are thunks that are evaluated at most once, so terms are evaluated lazily like in normal order reduction.
+const RUST_RUNTIME: &str = r#"#![allow(dead_code)]
+use std::{cell::OnceCell, rc::Rc};
+
+#[derive(Clone)]
+pub enum Value {
+	Fun(Rc<dyn Fn(Thunk) -> Value>),
+	/// Counter used to read back church numerals and booleans
+	Num(u64),
+	/// Result of applying something that isn't a function
+	Stuck,
+}
+
+/// Argument that is evaluated the first time it is needed
+#[derive(Clone)]
+pub struct Thunk(Rc<(OnceCell<Value>, Box<dyn Fn() -> Value>)>);
+impl Thunk {
+	fn new(code: impl Fn() -> Value + 'static) -> Self {
+		Thunk(Rc::new((OnceCell::new(), Box::new(code))))
+	}
+	fn value(value: Value) -> Self {
+		Thunk(Rc::new((OnceCell::from(value), Box::new(|| Value::Stuck))))
+	}
+	fn force(&self) -> Value {
+		self.0.0.get_or_init(|| (self.0.1)()).clone()
+	}
+}
+
+fn fun(func: impl Fn(Thunk) -> Value + 'static) -> Value {
+	Value::Fun(Rc::new(func))
+}
+fn apply(func: Value, arg: Thunk) -> Value {
+	match func {
+		Value::Fun(func) => func(arg),
+		_ => Value::Stuck,
+	}
+}
+
+/// Read back church numeral `[f x] f (f .. x)`
+pub fn to_nat(value: Value) -> Option<u64> {
+	let succ = fun(|n| match n.force() {
+		Value::Num(n) => Value::Num(n + 1),
+		_ => Value::Stuck,
+	});
+	match apply(apply(value, Thunk::value(succ)), Thunk::value(Value::Num(0))) {
+		Value::Num(n) => Some(n),
+		_ => None,
+	}
+}
+
+/// Read back church boolean `[x y] x` or `[x y] y`
+pub fn to_bool(value: Value) -> Option<bool> {
+	match apply(apply(value, Thunk::value(Value::Num(1))), Thunk::value(Value::Num(0))) {
+		Value::Num(1) => Some(true),
+		Value::Num(0) => Some(false),
+		_ => None,
+	}
+}
+"#;
+
+/// Runtime shared by all generated JavaScript modules, see `RUST_RUNTIME`
+const JS_RUNTIME: &str = r#""use strict";
+
+/** Argument that is evaluated the first time it is needed */
+class Thunk {
+	constructor(code) {
+		this.code = code;
+		this.evaluated = false;
+		this.value = undefined;
+	}
+	static of(value) {
+		const thunk = new Thunk(null);
+		thunk.evaluated = true;
+		thunk.value = value;
+		return thunk;
+	}
+	force() {
+		if (!this.evaluated) {
+			this.value = this.code();
+			this.evaluated = true;
+			this.code = null;
+		}
+		return this.value;
+	}
+}
+
+const apply = (func, arg) => typeof func === "function" ? func(arg) : undefined;
+
+/** Read back church numeral `[f x] f (f .. x)` */
+export const toNat = (value) => {
+	const succ = (n) => {
+		const k = n.force();
+		return typeof k === "number" ? k + 1 : undefined;
+	};
+	const n = apply(apply(value, Thunk.of(succ)), Thunk.of(0));
+	return typeof n === "number" ? n : null;
+};
+
+/** Read back church boolean `[x y] x` or `[x y] y` */
+export const toBool = (value) => {
+	const b = apply(apply(value, Thunk.of(1)), Thunk.of(0));
+	return b === 1 ? true : b === 0 ? false : null;
+};
+"#;
+
+/// Levels of the lambdas whose variables occur in `tree`, these have to be captured by closures
+fn captured(tree: &BindSubTree) -> Vec<usize> {
+	let mut levels = Vec::new();
+	let mut stack = vec![tree];
+	while let Some(tree) = stack.pop() {
+		match tree {
+			BindTree::None => {}
+			BindTree::End(level) => levels.push(*level),
+			BindTree::Branch(left, right) => stack.extend([right, left]),
+		}
+	}
+	levels.sort_unstable();
+	levels.dedup();
+	levels
+}
+
+/// Rust closures own what they capture, so captured thunks are cloned before every closure is created
+fn clone_captured(out: &mut String, levels: &[usize]) {
+	for level in levels {
+		let _ = write!(out, "let v{level} = v{level}.clone(); ");
+	}
+}
+
+/// Generate code that evaluates expression to a value
+fn generate(expr: &Expr, target: Target) -> Result<String, TranspileError> {
+	enum Task<'e, 't> {
+		/// Code evaluating expression
+		Value(&'e Expr<'e>, &'t BindSubTree<'t>, usize),
+		/// Code delaying expression as a thunk
+		Thunk(&'e Expr<'e>, &'t BindSubTree<'t>, usize),
+		Emit(&'static str),
+	}
+	let trees = &LinkArena::new();
+	let mut out = String::new();
+	let mut tasks = vec![Task::Value(expr, BindTree::NONE, 0)];
+	while let Some(task) = tasks.pop() {
+		match task {
+			Task::Value(Expr::Variable, tree, _) => match tree {
+				BindTree::End(level) => { let _ = write!(out, "v{level}.force()"); }
+				BindTree::None => return Err(TranspileError::FreeVariable),
+				BindTree::Branch(..) => return Err(TranspileError::UnexpectedBranch),
+			}
+			Task::Value(Expr::Lambda { bind, expr }, tree, depth) => {
+				let level = depth + 1;
+				let param = if matches!(bind, Binding::None) { "_".to_owned() } else { format!("v{level}") };
+				let mut body_tree = tree;
+				body_tree.push_binding(trees, level, bind)?;
+				match target {
+					Target::Rust => {
+						let captured = captured(tree);
+						if captured.is_empty() {
+							let _ = write!(out, "fun(move |{param}| ");
+							tasks.push(Task::Emit(")"));
+						} else {
+							out.push_str("{ ");
+							clone_captured(&mut out, &captured);
+							let _ = write!(out, "fun(move |{param}| ");
+							tasks.push(Task::Emit(") }"));
+						}
+					}
+					Target::JavaScript => {
+						let _ = write!(out, "(({param}) => ");
+						tasks.push(Task::Emit(")"));
+					}
+				}
+				tasks.push(Task::Value(expr, body_tree, level));
+			}
+			Task::Value(Expr::Application { func, args }, tree, depth) => {
+				let (func_tree, args_tree) = tree.split()?;
+				out.push_str("apply(");
+				tasks.push(Task::Emit(")"));
+				tasks.push(Task::Thunk(args, args_tree, depth));
+				tasks.push(Task::Emit(", "));
+				tasks.push(Task::Value(func, func_tree, depth));
+			}
+			// Variables are already thunks, pass them on so they are shared
+			Task::Thunk(Expr::Variable, BindTree::End(level), _) => match target {
+				Target::Rust => { let _ = write!(out, "v{level}.clone()"); }
+				Target::JavaScript => { let _ = write!(out, "v{level}"); }
+			}
+			Task::Thunk(expr, tree, depth) => {
+				match target {
+					Target::Rust => {
+						let captured = captured(tree);
+						if captured.is_empty() {
+							out.push_str("Thunk::new(move || ");
+							tasks.push(Task::Emit(")"));
+						} else {
+							out.push_str("{ ");
+							clone_captured(&mut out, &captured);
+							out.push_str("Thunk::new(move || ");
+							tasks.push(Task::Emit(") }"));
+						}
+					}
+					Target::JavaScript => {
+						out.push_str("new Thunk(() => ");
+						tasks.push(Task::Emit(")"));
+					}
+				}
+				tasks.push(Task::Value(expr, tree, depth));
+			}
+			Task::Emit(string) => out.push_str(string),
+		}
+	}
+	Ok(out)
+}
+
+/// Generate a self-contained Rust or JavaScript module exporting `term`, which evaluates the named expression, and helpers to read back church numerals and booleans.
+/// Evaluation recurses on the native stack, so reading back large numerals may need a bigger stack (e.g. `node --stack-size`).
+pub fn transpile(name: &Name, target: Target) -> Result<String, TranspileError> {
+	let expr = match name.object {
+		NamedObject::Expr(expr) => expr,
+		NamedObject::Namespace(_) => return Err(TranspileError::NotAnExpression(name.string.clone())),
+	};
+	let code = generate(expr, target)?;
+	let string = name.string;
+	Ok(match target {
+		Target::Rust => format!("//! Generated from `{string}`: {expr}\n\n{RUST_RUNTIME}\n/// Evaluates `{string}`\npub fn term() -> Value {{\n\t{code}\n}}\n"),
+		Target::JavaScript => format!("// Generated from `{string}`: {expr}\n\n{JS_RUNTIME}\n/** Evaluates `{string}` */\nexport const term = () => {code};\n"),
+	})
+}
+
+#[test]
+fn transpile_test() {
+	use crate::{name::NamespaceMut, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &NamespaceMut::new();
+	namespace.add("true", parse("[x y] x", namespace, exprs).unwrap(), exprs);
+	namespace.add("apply", parse("[f x] f x", namespace, exprs).unwrap(), exprs);
+	namespace.add("open", parse("[x] x free", namespace, exprs).unwrap(), exprs);
+	let find = |string: &str| namespace.find(|name| name.string == string).unwrap();
+
+	assert_eq!(generate(parse("[x y] x", namespace, exprs).unwrap(), Target::Rust).unwrap(), "fun(move |v1| { let v1 = v1.clone(); fun(move |_| v1.force()) })");
+	assert_eq!(generate(parse("[x y] x", namespace, exprs).unwrap(), Target::JavaScript).unwrap(), "((v1) => ((_) => v1.force()))");
+	assert_eq!(generate(parse("[f x] f x", namespace, exprs).unwrap(), Target::Rust).unwrap(), "fun(move |v1| { let v1 = v1.clone(); fun(move |v2| apply(v1.force(), v2.clone())) })");
+	assert_eq!(generate(parse("[f] f (f f)", namespace, exprs).unwrap(), Target::JavaScript).unwrap(), "((v1) => apply(v1.force(), new Thunk(() => apply(v1.force(), v1))))");
+
+	let module = transpile(find("true"), Target::Rust).unwrap();
+	assert!(module.starts_with("//! Generated from `true`") && module.contains("pub fn term() -> Value"));
+	assert!(transpile(find("apply"), Target::JavaScript).unwrap().contains("export const term = () => "));
+	assert!(matches!(transpile(find("open"), Target::Rust), Err(TranspileError::FreeVariable)));
+}
+
+#[test]
+fn transpile_run_test() {
+	use std::process::Command;
+	use crate::{name::church_namespace, parse::parse};
+
+	let exprs = &LinkArena::new();
+	let namespace = &church_namespace(exprs);
+
+	// Compile a program printing the numerals the generated modules evaluate to.
+	// `pow 3 0` reduces to `[x] x`, which isn't decoded as a numeral but reads back as one.
+	let terms = ["mul 3 4", "add (pow 2 5) 1", "pow 3 0", "succ (mul 0 7)"];
+	let expected = "Some(12)\nSome(33)\nSome(1)\nSome(1)\n";
+	let mut program = String::new();
+	for (i, term) in terms.into_iter().enumerate() {
+		let expr = parse(term, namespace, exprs).unwrap();
+		let name = format!("term{i}");
+		namespace.add(&name, expr, exprs);
+		let module = transpile(namespace.find(|item| *item.string == name).unwrap(), Target::Rust).unwrap();
+		let _ = writeln!(program, "mod {name} {{\n{module}}}");
+	}
+	let prints: String = (0..terms.len()).map(|i| format!("println!(\"{{:?}}\", term{i}::to_nat(term{i}::term())); ")).collect();
+	let _ = writeln!(program, "fn main() {{ {prints}}}");
+
+	let dir = std::env::temp_dir().join(format!("transpile_run_test_{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("main.rs"), program).unwrap();
+	let status = Command::new("rustc").args(["--edition", "2021", "-o"]).arg(dir.join("main")).arg(dir.join("main.rs")).status().unwrap();
+	assert!(status.success());
+	let output = Command::new(dir.join("main")).output().unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}